@@ -0,0 +1,147 @@
+//! Pin benchmark processes to CPUs, so runs in parallel do not compete for the same cores.
+
+use libc;
+use std::io;
+use std::mem;
+use std::sync::Mutex;
+
+/// Parse a list of CPUs like "2-5" or "0,2,8-11". Returns None if the list is invalid.
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+
+    for part in list.split(',').map(str::trim) {
+        let mut bounds = part.splitn(2, '-');
+        let first = bounds.next()?.trim().parse::<usize>().ok()?;
+        let last = match bounds.next() {
+            Some(last) => last.trim().parse::<usize>().ok()?,
+            None => first,
+        };
+        if last < first || last >= libc::CPU_SETSIZE as usize {
+            return None;
+        }
+        cpus.extend(first..last + 1);
+    }
+    cpus.sort();
+    cpus.dedup();
+    Some(cpus)
+}
+
+/// Compact form of a list of CPUs, consecutive CPUs are written as a range.
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < cpus.len() {
+        let mut j = i;
+        while j + 1 < cpus.len() && cpus[j + 1] == cpus[j] + 1 {
+            j += 1;
+        }
+        if i == j {
+            parts.push(cpus[i].to_string());
+        } else {
+            parts.push(format!("{}-{}", cpus[i], cpus[j]));
+        }
+        i = j + 1;
+    }
+    parts.join(",")
+}
+
+/// CPUs this process is allowed to run on.
+pub fn available_cpus() -> Vec<usize> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    let ret = unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if ret != 0 {
+        return Vec::new();
+    }
+    (0..libc::CPU_SETSIZE as usize).filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) }).collect()
+}
+
+/// Build the set for `sched_setaffinity` from a list of CPUs.
+pub fn cpu_set(cpus: &[usize]) -> libc::cpu_set_t {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    for &cpu in cpus {
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    set
+}
+
+/// Restrict the calling process to the CPUs in `set`. Only does a system call, so it can be used
+/// between fork and exec.
+pub fn set_affinity(set: &libc::cpu_set_t) -> io::Result<()> {
+    let ret = unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), set) };
+    if ret == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Disjoint sets of CPUs, one for every worker of the threadpool. A run takes a set while it is
+/// running and gives it back afterwards, so every concurrent run has cores of its own.
+#[derive(Debug)]
+pub struct CpuSlots {
+    free: Mutex<Vec<Vec<usize>>>,
+}
+
+impl CpuSlots {
+    /// Split `cpus` into `workers` sets of the same size, left over CPUs stay unused.
+    /// Returns None if there are less CPUs than workers.
+    pub fn split(cpus: &[usize], workers: usize) -> Option<CpuSlots> {
+        if workers == 0 || cpus.len() < workers {
+            return None;
+        }
+        let size = cpus.len() / workers;
+        let sets = cpus.chunks(size).take(workers).map(<[usize]>::to_vec).collect();
+        Some(CpuSlots { free: Mutex::new(sets) })
+    }
+
+    /// Take a free set. There are as many sets as workers, so one is always free.
+    pub fn take(&self) -> Vec<usize> {
+        self.free.lock().unwrap().pop().expect("More concurrent runs than CPU sets")
+    }
+
+    pub fn give_back(&self, cpus: Vec<usize>) {
+        self.free.lock().unwrap().push(cpus);
+    }
+}
+
+
+// --------------------- tests for the cpu affinity ---------------------------
+
+#[test]
+fn test_parse_cpu_list() {
+    assert_eq!(parse_cpu_list("2-5"), Some(vec![2, 3, 4, 5]));
+    assert_eq!(parse_cpu_list("0, 2,8-9"), Some(vec![0, 2, 8, 9]));
+    assert_eq!(parse_cpu_list("3"), Some(vec![3]));
+    assert_eq!(format_cpu_list(&[0, 2, 3, 4, 8]), "0,2-4,8");
+    assert_eq!(format_cpu_list(&parse_cpu_list("0,2,8-11").unwrap()), "0,2,8-11");
+}
+
+#[test]
+fn test_parse_cpu_list_failcondition() {
+    assert_eq!(parse_cpu_list(""), None);
+    assert_eq!(parse_cpu_list("5-2"), None);
+    assert_eq!(parse_cpu_list("a-b"), None);
+    assert_eq!(parse_cpu_list("100000"), None);
+}
+
+#[test]
+fn test_cpu_slots() {
+    let cpus: Vec<usize> = (0..16).collect();
+    let slots = CpuSlots::split(&cpus, 4).unwrap();
+
+    let mut taken: Vec<Vec<usize>> = (0..4).map(|_| slots.take()).collect();
+    assert!(taken.iter().all(|set| set.len() == 4));
+    taken.sort();
+    assert_eq!(taken.concat(), cpus);
+    slots.give_back(taken.pop().unwrap());
+    assert_eq!(slots.take(), vec![12, 13, 14, 15]);
+
+    // left over CPUs are not used
+    let slots = CpuSlots::split(&cpus[..7], 3).unwrap();
+    assert_eq!(slots.take().len(), 2);
+    assert!(CpuSlots::split(&cpus[..2], 3).is_none());
+}
+
+#[test]
+fn test_available_cpus() {
+    let cpus = available_cpus();
+    assert!(!cpus.is_empty());
+    // pinning to the CPUs we already have changes nothing
+    assert!(set_affinity(&cpu_set(&cpus)).is_ok());
+}
@@ -1,95 +1,912 @@
-/// Logic and functionality to actually perform the benchmarks.
-///
+//! Logic and functionality to actually perform the benchmarks.
+//!
 
 // subprocesses to call the command we want to measure
 use std::process::{Command, Stdio, ExitStatus};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::io;
+use std::mem;
+
+// output of the runs
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process;
+use regex::Regex;
 
 // parallelism
 use threadpool::ThreadPool;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{Sender, RecvTimeoutError, channel};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+
+// signals to stop processes that take too long, resource usage of finished processes
+use libc;
 
 // time measurements
 use std::time::{Instant, Duration};
 
+use std::collections::BTreeMap;
+
+use affinity;
+use affinity::CpuSlots;
+use random::Rng;
+use requirements::Requirement;
+use statistics;
+
 /// Define values used to configure a benchmark run.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RunConfig {
     pub name: String,
     pub description: String,
     pub count: i64,
+    pub warmup: i64, // runs before the measurement that are not recorded
+
+    // with a target for the confidence interval or a time budget the number of runs is decided
+    // while running
+    pub min_runs: i64,
+    pub max_runs: i64,
+    pub target_rel_ci: Option<f64>, // relative half width of the CI of the average in percent
+    pub time_budget: Option<Duration>, // time all measured runs of the case may take together
 
     pub command: String,
     pub args: Vec<String>, // empty vector if no args were configured
+    pub shell: Option<String>, // optional, shell that runs the command line
+    pub subtract_shell_startup: bool, // measure the start of the shell and subtract it
     pub directory: String, // optional
-    pub environment: Vec<String>, // optional
+    pub environment: Vec<(String, String)>, // optional, a case list replaces the global one
+    pub configured_environment: Vec<(String, String)>, // as configured, before `${VAR}` expansion
+    pub clear_environment: bool, // start the process without inherited variables
+    pub unset_environment: Vec<String>, // optional, variables removed for the process
+    pub cpus: Option<Vec<usize>>, // optional, CPUs the command is pinned to
+
+    pub capture: Capture, // runs that keep their stdout and stderr
+    pub log_directory: String, // captured output goes to <log_directory>/<case>/
+    // a run that does not meet these expectations is invalid and not part of the statistics
+    pub expect_stdout: Option<Regex>,
+    pub expect_stderr: Option<Regex>,
+    pub expect_exit_code: Option<Vec<i32>>, // if not set, any exit code is accepted
+
+    pub timeout: Option<Duration>, // optional, kill the run if it takes longer
+    pub timeout_grace: Duration, // time between SIGTERM and SIGKILL after a timeout
+
+    // optional shell commands executed around the benchmark, outside of the measurement
+    pub setup: Option<String>, // once before the first run of the case
+    pub teardown: Option<String>, // once after the last run of the case
+    pub prepare: Option<String>, // before every run
+    pub cleanup: Option<String>, // after every run
+
+    pub parameters: BTreeMap<String, String>, // values of the matrix combination, if any
+    pub requirements: Vec<Requirement>, // checked by `macrobm check`
+}
+
+impl RunConfig {
+    /// Cases with a target for the confidence interval or a time budget decide while running
+    /// how many runs they need.
+    pub fn is_adaptive(&self) -> bool {
+        self.target_rel_ci.is_some() || self.time_budget.is_some()
+    }
+
+    /// Number of measured runs that will be done at most.
+    pub fn planned_runs(&self) -> i64 {
+        if self.is_adaptive() { self.max_runs } else { self.count.max(0) }
+    }
+}
+
+/// Which runs keep their output in log files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capture {
+    None,
+    /// Runs that did not exit successfully or did not meet the expectations of the case.
+    Failures,
+    All,
+}
+
+impl Capture {
+    pub fn from_name(name: &str) -> Option<Capture> {
+        match name {
+            "none" => Some(Capture::None),
+            "failures" => Some(Capture::Failures),
+            "all" => Some(Capture::All),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Capture::None => "none",
+            Capture::Failures => "failures",
+            Capture::All => "all",
+        }
+    }
+}
+
+/// Why an adaptive case stopped running.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// The confidence interval is as narrow as the target.
+    TargetReached,
+    /// `max_runs` were done before the target was reached.
+    MaxRuns,
+    /// The time budget was used up before the target was reached.
+    TimeBudget,
+}
+
+/// State of an adaptive case when it stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stop {
+    pub reason: StopReason,
+    pub runs: usize,
+    pub rel_ci: f64, // relative half width of the CI of the average in percent
+}
+
+/// Order in which the measured runs of all cases are started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunOrder {
+    /// All runs of a case before the runs of the next case.
+    Sequential,
+    /// One run of every case in turn, so slow drift of the machine affects all cases alike.
+    RoundRobin,
+    /// Random order from this seed, the same seed gives the same order again.
+    Shuffled(u64),
+}
+
+impl RunOrder {
+    /// Order for the name in the configuration, a shuffled order uses `seed`.
+    pub fn from_name(name: &str, seed: u64) -> Option<RunOrder> {
+        match name {
+            "sequential" => Some(RunOrder::Sequential),
+            "round-robin" => Some(RunOrder::RoundRobin),
+            "shuffled" => Some(RunOrder::Shuffled(seed)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RunOrder::Sequential => "sequential",
+            RunOrder::RoundRobin => "round-robin",
+            RunOrder::Shuffled(_) => "shuffled",
+        }
+    }
+}
+
+/// How a single run of a benchmark ended.
+#[derive(Debug)]
+pub enum Outcome {
+    /// The process terminated on its own with a status the case accepts.
+    Exited(ExitStatus),
+    /// The process terminated with a non zero exit code or by a signal and the case does not
+    /// expect specific exit codes.
+    Failed(ExitStatus),
+    /// The process took longer than the configured timeout and was killed.
+    TimedOut,
+    /// The process could not be started, the message contains the OS error.
+    SpawnError(String),
+    /// Waiting for the process failed, the message contains the OS error.
+    WaitError(String),
+    /// The setup or prepare command of the case failed, so the command was not run.
+    HookError(String),
+    /// The process finished with this status, but its exit code or output did not meet the
+    /// expectations.
+    Invalid(ExitStatus, String),
+}
+
+/// Names of the resource usage metrics, in the order they are reported.
+pub const USAGE_METRICS: [&str; 7] = ["user_time", "sys_time", "max_rss", "minor_faults",
+                                      "major_faults", "voluntary_switches",
+                                      "involuntary_switches"];
+
+/// Resources a finished process used, as reported by `wait4`.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceUsage {
+    pub user_time: f64, // seconds spent in user mode
+    pub sys_time: f64, // seconds spent in the kernel
+    pub max_rss: i64, // maximum resident set size in kilobytes
+    pub minor_faults: i64, // page faults without I/O
+    pub major_faults: i64, // page faults that required I/O
+    pub voluntary_switches: i64, // context switches because the process waited
+    pub involuntary_switches: i64, // context switches because the time slice was used up
+}
+
+impl ResourceUsage {
+    // c_long is not i64 on every platform
+    #[allow(clippy::unnecessary_cast)]
+    fn from_rusage(usage: &libc::rusage) -> ResourceUsage {
+        ResourceUsage {
+            user_time: convert_timeval_to_seconds(&usage.ru_utime),
+            sys_time: convert_timeval_to_seconds(&usage.ru_stime),
+            max_rss: usage.ru_maxrss as i64,
+            minor_faults: usage.ru_minflt as i64,
+            major_faults: usage.ru_majflt as i64,
+            voluntary_switches: usage.ru_nvcsw as i64,
+            involuntary_switches: usage.ru_nivcsw as i64,
+        }
+    }
+
+    /// All values together with their name and unit, in the order of `USAGE_METRICS`.
+    pub fn metrics(&self) -> Vec<(&'static str, f64, &'static str)> {
+        let values = [(self.user_time, "s"),
+                      (self.sys_time, "s"),
+                      (self.max_rss as f64, "kB"),
+                      (self.minor_faults as f64, ""),
+                      (self.major_faults as f64, ""),
+                      (self.voluntary_switches as f64, ""),
+                      (self.involuntary_switches as f64, "")];
+        USAGE_METRICS.iter().zip(values.iter())
+            .map(|(name, &(value, unit))| (*name, value, unit))
+            .collect()
+    }
 }
 
 /// Data one benchmark run produces.
 #[derive(Debug)]
 pub struct Report {
     pub name: String,
-    pub duration: f32,
-    pub ecode: ExitStatus,
+    pub duration: f64,
+    pub outcome: Outcome,
+    pub usage: Option<ResourceUsage>, // only known for processes that could be waited for
+    pub warmup: bool, // warmup runs are not part of the statistics
+    pub stop: Option<Stop>, // set on the last run of an adaptive case
+    pub cpus: Option<Vec<usize>>, // CPUs the command was pinned to, if any
+    pub shell_startup: Option<f64>, // seconds subtracted from the duration for the shell
+    pub hook_errors: Vec<String>, // cleanup and teardown that failed after the measurement
 }
 
 impl Report {
-    pub fn new(name: String, dur: Duration, code: ExitStatus) -> Report {
+    pub fn new(name: String, dur: Duration, outcome: Outcome) -> Report {
         Report {
-            name: name,
+            name,
             duration: convert_duration_to_seconds(dur),
-            ecode: code,
+            outcome,
+            usage: None,
+            warmup: false,
+            stop: None,
+            cpus: None,
+            shell_startup: None,
+            hook_errors: Vec::new(),
         }
     }
 }
 
-fn convert_duration_to_seconds(dur: Duration) -> f32 {
-    dur.as_secs() as f32 + dur.subsec_nanos() as f32 / 1000000000.
+fn convert_duration_to_seconds(dur: Duration) -> f64 {
+    dur.as_secs() as f64 + dur.subsec_nanos() as f64 / 1000000000.
 }
 
-/// Start all benchmarks in a threadpool and configure a channel to receive a Report for every
-/// finished run.
-pub fn do_benchmark(pool: &ThreadPool,
-                    name: &str,
-                    channel_trans: Sender<Report>,
-                    config: &RunConfig) {
-    for _ in 0..config.count {
+fn convert_timeval_to_seconds(time: &libc::timeval) -> f64 {
+    time.tv_sec as f64 + time.tv_usec as f64 / 1000000.
+}
+
+/// Set when SIGINT arrives, the session stops and keeps what was measured so far.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // a second Ctrl-C terminates at once
+    unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+}
+
+/// Catch SIGINT instead of terminating, so the runs that are done can still be saved.
+/// Benchmarked commands get the default handling again when they are executed.
+pub fn catch_interrupt() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+}
+
+/// True once SIGINT arrived after `catch_interrupt`.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Start the measured runs of all cases in a threadpool, in the given order, and configure a
+/// channel to receive a Report for every finished run. The first run of a case that starts does
+/// the setup and the warmup runs, the other runs of the case wait for them.
+/// Adaptive cases take part in the order with their first batch, later batches are started when
+/// they are decided. They continue from the `done` progress of an earlier session, if any.
+/// With `slots` every run is pinned to a set of CPUs no other run uses.
+pub fn do_benchmarks(pool: &ThreadPool,
+                     cases: &BTreeMap<String, RunConfig>,
+                     done: &BTreeMap<String, AdaptiveProgress>,
+                     order: RunOrder,
+                     slots: Option<Arc<CpuSlots>>,
+                     channel_trans: Sender<Report>) -> Session {
+    let mut states = Vec::new();
+    let mut counts = Vec::new();
+
+    for (name, config) in cases {
+        let progress = done.get(name).cloned().unwrap_or_default();
+        let first_batch = if !config.is_adaptive() {
+            config.count.max(0) as usize
+        } else if progress.runs == 0 {
+            config.min_runs.min(config.max_runs).max(1) as usize
+        } else {
+            // a case that is already done gets no runs
+            next_batch(config, &progress.samples, progress.runs, progress.spent,
+                       pool.max_count()).unwrap_or(0)
+        };
+        states.push(Arc::new(CaseState {
+            name: name.clone(),
+            config: config.clone(),
+            slots: slots.clone(),
+            prepared: Mutex::new(None),
+            torn_down: AtomicBool::new(false),
+            remaining: AtomicUsize::new(first_batch),
+            started: AtomicUsize::new(0),
+            progress: Mutex::new(progress),
+        }));
+        counts.push(first_batch);
+    }
+
+    let runs: Vec<Arc<CaseState>> = run_order(&counts, order).into_iter()
+        .map(|case| states[case].clone())
+        .collect();
+    schedule_runs(pool, &runs, &channel_trans);
+    Session { pool: pool.clone(), cases: states }
+}
+
+/// Cases of a session whose runs are in the threadpool.
+pub struct Session {
+    pool: ThreadPool,
+    cases: Vec<Arc<CaseState>>,
+}
+
+impl Session {
+    /// Stop an interrupted session. No new runs start, the runs that are still going are waited
+    /// for and runs in an own process group are interrupted. Then every case that did its setup
+    /// gets its teardown. Returns the teardowns that failed, with the name of their case.
+    pub fn stop(&self) -> Vec<(String, String)> {
+        self.pool.join();
+        self.cases.iter()
+            .filter_map(|case| case.tear_down().err().map(|msg| (case.name.clone(), msg)))
+            .collect()
+    }
+}
+
+/// Indices of the cases in the order their runs are started, `counts` is the number of runs of
+/// every case.
+fn run_order(counts: &[usize], order: RunOrder) -> Vec<usize> {
+    let mut runs = Vec::new();
+    match order {
+        RunOrder::Sequential | RunOrder::Shuffled(_) => {
+            for (case, &count) in counts.iter().enumerate() {
+                runs.extend(std::iter::repeat_n(case, count));
+            }
+        }
+        RunOrder::RoundRobin => {
+            let rounds = counts.iter().cloned().max().unwrap_or(0);
+            for round in 0..rounds {
+                runs.extend((0..counts.len()).filter(|&case| counts[case] > round));
+            }
+        }
+    }
+
+    if let RunOrder::Shuffled(seed) = order {
+        // Fisher-Yates
+        let mut rng = Rng::new(seed);
+        for i in (1..runs.len()).rev() {
+            runs.swap(i, rng.below(i + 1));
+        }
+    }
+    runs
+}
+
+/// Shared state of a case while its runs are in the threadpool.
+struct CaseState {
+    name: String,
+    config: RunConfig,
+    slots: Option<Arc<CpuSlots>>, // CPU sets of the workers, if runs get exclusive cores
+    // outcome of the setup and warmups once they are done, with the startup time of the shell
+    // that is subtracted from every run
+    prepared: Mutex<Option<Result<Option<f64>, String>>>,
+    torn_down: AtomicBool, // the teardown ran, after the last run or after an interrupt
+    remaining: AtomicUsize, // runs of the current batch that are not finished yet
+    started: AtomicUsize, // measured runs that were started, numbers the log files
+    progress: Mutex<AdaptiveProgress>,
+}
+
+impl CaseState {
+    /// Do the setup and the warmup runs of the case if this did not happen yet.
+    fn prepare(&self, channel_trans: &Sender<Report>) -> Result<Option<f64>, String> {
+        let mut prepared = self.prepared.lock().unwrap();
+        if prepared.is_none() {
+            *prepared = Some(self.setup_and_warmup(channel_trans));
+        }
+        prepared.clone().unwrap()
+    }
+
+    fn setup_and_warmup(&self, channel_trans: &Sender<Report>) -> Result<Option<f64>, String> {
+        if let Some(ref setup) = self.config.setup {
+            if let Err(msg) = run_hook("setup", setup, &self.config) {
+                // every scheduled run must still be reported
+                for _ in 0..self.config.warmup.max(0) {
+                    let mut report = Report::new(self.name.clone(), Duration::from_secs(0),
+                                                 Outcome::HookError(msg.clone()));
+                    report.warmup = true;
+                    channel_trans.send(report).unwrap();
+                }
+                return Err(msg);
+            }
+        }
+
+        for i in 0..self.config.warmup {
+            if interrupted() {
+                break;
+            }
+            let label = format!("warmup_{}", i + 1);
+            let mut report = run_iteration(&self.name, &label, &self.config, self.slots.as_ref());
+            report.warmup = true;
+            channel_trans.send(report).unwrap();
+        }
+
+        match self.config.shell {
+            Some(ref shell) if self.config.subtract_shell_startup => {
+                measure_shell_startup(shell, &self.config).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Run the teardown once, if the setup of the case succeeded.
+    fn tear_down(&self) -> Result<(), String> {
+        let prepared = matches!(*self.prepared.lock().unwrap(), Some(Ok(_)));
+        if !prepared || self.torn_down.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        match self.config.teardown {
+            Some(ref teardown) => run_hook("teardown", teardown, &self.config),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Measurements of a case so far, adaptive cases decide with them about the next batch.
+#[derive(Debug, Clone, Default)]
+pub struct AdaptiveProgress {
+    pub samples: Vec<f64>, // durations of the runs that are part of the statistics
+    pub runs: usize, // finished runs, including failed ones
+    pub spent: Duration, // time of all finished runs including their hooks, not the time queued
+}
+
+/// Put one run for every entry of `runs` into the threadpool.
+fn schedule_runs(pool: &ThreadPool, runs: &[Arc<CaseState>], channel_trans: &Sender<Report>) {
+    for case in runs {
         // threads need own version of the data
-        let name = name.to_string();
-        let cmd = config.command.clone();
-        let args = config.args.clone();
+        let pool_handle = pool.clone();
+        let case = case.clone();
         let tx = channel_trans.clone();
-        let dir = config.directory.clone();
-        // let env = config.environment.clone();
-
-        pool.execute(move || {
-            let start_time = Instant::now();
-            let mut process = Command::new(&cmd);
-            process.args(&args)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .current_dir(&dir);
-
-            match process.spawn() {
-                Ok(mut child) => {
-                    match child.wait() {
-                        Ok(ecode) =>  {
-                            let execution_time = start_time.elapsed();
-                            tx.send(Report::new(name, execution_time, ecode)).unwrap();
-                        }
-                        Err(_) => {
-                            println!("Program did not finish as expected");
-                            println!("Command: {}", &cmd);
-                            println!("Workdir: {}", &dir);
-                        }
-                    }
-                }
-                Err(_) => {
-                    println!("Could not start benchmark {}", &name);
-                    println!("Command: {}", &cmd);
-                    println!("Workdir: {}", &dir);
-                }
+
+        pool.execute(move || run_scheduled(&pool_handle, &case, tx));
+    }
+}
+
+/// Do one measured run of a case. The run finishing the last batch of the case executes the
+/// teardown, for adaptive cases it decides if another batch is necessary or reports why the case
+/// stopped.
+fn run_scheduled(pool: &ThreadPool, case: &Arc<CaseState>, channel_trans: Sender<Report>) {
+    // nobody waits for the runs of an interrupted session
+    if interrupted() {
+        return;
+    }
+    let prepared = case.prepare(&channel_trans);
+    if interrupted() {
+        return;
+    }
+    let start = Instant::now();
+    let mut report = match prepared {
+        Ok(shell_startup) => {
+            let label = format!("run_{}", case.started.fetch_add(1, Ordering::SeqCst) + 1);
+            let mut report = run_iteration(&case.name, &label, &case.config, case.slots.as_ref());
+            if let Some(startup) = shell_startup {
+                report.duration = (report.duration - startup).max(0.);
+                report.shell_startup = shell_startup;
+            }
+            report
+        }
+        Err(ref msg) => Report::new(case.name.clone(), Duration::from_secs(0),
+                                    Outcome::HookError(msg.clone())),
+    };
+
+    let next = {
+        let mut progress = case.progress.lock().unwrap();
+        progress.runs += 1;
+        progress.spent += start.elapsed();
+        if let Outcome::Exited(_) = report.outcome {
+            progress.samples.push(report.duration);
+        }
+
+        if case.remaining.fetch_sub(1, Ordering::SeqCst) != 1 {
+            None
+        } else if prepared.is_err() || !case.config.is_adaptive() {
+            Some(Err(None))
+        } else {
+            let next = next_batch(&case.config, &progress.samples, progress.runs,
+                                  progress.spent, pool.max_count());
+            if let Ok(size) = next {
+                case.remaining.store(size, Ordering::SeqCst);
             }
-        });
+            Some(next.map_err(Some))
+        }
+    };
+
+    match next {
+        Some(Ok(size)) => schedule_runs(pool, &vec![case.clone(); size], &channel_trans),
+        Some(Err(stop)) => {
+            if let Err(msg) = case.tear_down() {
+                report.hook_errors.push(msg);
+            }
+            report.stop = stop;
+        }
+        None => (),
+    }
+    channel_trans.send(report).unwrap();
+}
+
+/// Decide if an adaptive case needs more runs after `runs` runs that took `spent`. Returns the
+/// size of the next batch or why the case is done. Cases with a target run batches of
+/// `batch_size`, cases with only a time budget schedule as many runs as are estimated to fit.
+fn next_batch(config: &RunConfig, samples: &[f64], runs: usize, spent: Duration,
+              batch_size: usize) -> Result<usize, Stop> {
+    let rel_ci = statistics::relative_ci(samples);
+    let stop = |reason| Err(Stop { reason, runs, rel_ci });
+    let done = runs as i64;
+    let left = (config.max_runs - done).max(0) as usize;
+
+    if done < config.min_runs {
+        return Ok(((config.min_runs - done) as usize).min(left));
+    }
+    if let Some(target) = config.target_rel_ci {
+        if rel_ci <= target {
+            return stop(StopReason::TargetReached);
+        }
+    }
+    if left == 0 {
+        return stop(StopReason::MaxRuns);
+    }
+
+    let mut size = if config.target_rel_ci.is_some() { batch_size.max(1) } else { left };
+    if let Some(budget) = config.time_budget {
+        if spent >= budget {
+            return stop(StopReason::TimeBudget);
+        }
+        // the runs so far estimate what one more run costs
+        let cost = convert_duration_to_seconds(spent) / runs.max(1) as f64;
+        if cost > 0. {
+            let fit = (convert_duration_to_seconds(budget - spent) / cost) as usize;
+            if fit == 0 {
+                return stop(StopReason::TimeBudget);
+            }
+            size = size.min(fit);
+        }
+    }
+    Ok(size.min(left))
+}
+
+/// Run the command once, surrounded by the prepare and cleanup hooks of the case. The command
+/// is pinned to the CPUs of the case or, with `slots`, to a free CPU set of the workers.
+/// `label` names the log files of the run. A failing cleanup keeps the measurement of the run
+/// and is reported with it.
+fn run_iteration(name: &str, label: &str, config: &RunConfig,
+                 slots: Option<&Arc<CpuSlots>>) -> Report {
+    if let Some(ref prepare) = config.prepare {
+        if let Err(msg) = run_hook("prepare", prepare, config) {
+            return Report::new(name.to_string(), Duration::from_secs(0), Outcome::HookError(msg));
+        }
+    }
+
+    let output = match RunOutput::create(name, label, config) {
+        Ok(output) => output,
+        Err(e) => {
+            let msg = format!("could not create the log directory {}: {}",
+                              config.log_directory, e);
+            return Report::new(name.to_string(), Duration::from_secs(0),
+                               Outcome::SpawnError(msg));
+        }
+    };
+
+    let mut report = match (config.cpus.as_ref(), slots) {
+        (Some(cpus), _) => run_once(name, config, Some(cpus), output.as_ref()),
+        (None, Some(slots)) => {
+            let cpus = slots.take();
+            let report = run_once(name, config, Some(&cpus), output.as_ref());
+            slots.give_back(cpus);
+            report
+        }
+        (None, None) => run_once(name, config, None, output.as_ref()),
+    };
+
+    if let Outcome::Exited(ecode) = report.outcome {
+        if let Err(msg) = check_expectations(ecode, config, output.as_ref()) {
+            report.outcome = Outcome::Invalid(ecode, msg);
+        } else if config.expect_exit_code.is_none() && !ecode.success() {
+            report.outcome = Outcome::Failed(ecode);
+        }
+    }
+    if let Some(output) = output {
+        let failed = !matches!(report.outcome, Outcome::Exited(_));
+        if !(config.capture == Capture::All || config.capture == Capture::Failures && failed) {
+            output.remove();
+        }
+    }
+
+    if let Some(ref cleanup) = config.cleanup {
+        if let Err(msg) = run_hook("cleanup", cleanup, config) {
+            report.hook_errors.push(msg);
+        }
+    }
+    report
+}
+
+/// Files the stdout and stderr of one run are written to.
+struct RunOutput {
+    stdout: PathBuf,
+    stderr: PathBuf,
+}
+
+impl RunOutput {
+    /// Files for the run `label` of a case. Runs that may keep their output write into the log
+    /// directory, runs whose output is only checked into the temporary directory. Returns None
+    /// if the output is not needed at all.
+    fn create(name: &str, label: &str, config: &RunConfig) -> io::Result<Option<RunOutput>> {
+        let checked = config.expect_stdout.is_some() || config.expect_stderr.is_some();
+        let name = file_name(name);
+        let base = match config.capture {
+            Capture::None if !checked => return Ok(None),
+            Capture::None => env::temp_dir().join(format!("macrobm_{}_{}_{}", process::id(),
+                                                          name, label)),
+            Capture::Failures | Capture::All => {
+                let dir = Path::new(&config.log_directory).join(name);
+                fs::create_dir_all(&dir)?;
+                dir.join(label)
+            }
+        };
+
+        Ok(Some(RunOutput {
+            stdout: base.with_extension("stdout"),
+            stderr: base.with_extension("stderr"),
+        }))
+    }
+
+    fn remove(&self) {
+        let _ = fs::remove_file(&self.stdout);
+        let _ = fs::remove_file(&self.stderr);
+    }
+}
+
+/// Case names can contain characters that do not belong into a file name.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.=,".contains(c) { c } else { '_' })
+        .collect()
+}
+
+/// Check the exit code and the output of a finished run against the expectations of the case.
+fn check_expectations(ecode: ExitStatus, config: &RunConfig,
+                      output: Option<&RunOutput>) -> Result<(), String> {
+    if let Some(ref codes) = config.expect_exit_code {
+        if !ecode.code().map(|code| codes.contains(&code)).unwrap_or(false) {
+            return Err(format!("{} instead of exit code {:?}", ecode, codes));
+        }
+    }
+
+    let streams = [("stdout", &config.expect_stdout, output.map(|o| &o.stdout)),
+                   ("stderr", &config.expect_stderr, output.map(|o| &o.stderr))];
+    for &(stream, expected, file) in &streams {
+        if let (Some(regex), Some(file)) = (expected.as_ref(), file) {
+            let content = fs::read(file).map_err(|e| format!("could not read {}: {}", stream, e))?;
+            if !regex.is_match(&String::from_utf8_lossy(&content)) {
+                return Err(format!("{} does not match `{}`", stream, regex));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Execute a hook through `sh -c` with the directory and environment of the case.
+fn run_hook(kind: &str, hook: &str, config: &RunConfig) -> Result<(), String> {
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(hook);
+    configure_process(&mut process, config);
+
+    match process.status() {
+        Ok(ecode) if ecode.success() => Ok(()),
+        Ok(ecode) => Err(format!("{} `{}` failed with {}", kind, hook, ecode)),
+        Err(e) => Err(format!("{} `{}` could not start: {}", kind, hook, e)),
+    }
+}
+
+/// Apply the directory and environment of the case to a process.
+fn configure_process(process: &mut Command, config: &RunConfig) {
+    process.stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .current_dir(&config.directory);
+    if config.clear_environment {
+        process.env_clear();
+    }
+    for var in &config.unset_environment {
+        process.env_remove(var);
+    }
+    process.envs(config.environment.iter().cloned());
+}
+
+/// Execute the configured command a single time and measure how long it takes.
+/// Every run produces a report, even if the command could not be started.
+fn run_once(name: &str, config: &RunConfig, cpus: Option<&Vec<usize>>,
+            output: Option<&RunOutput>) -> Report {
+    let mut process = match config.shell {
+        Some(ref shell) => {
+            let mut process = Command::new(shell);
+            process.arg("-c").arg(shell_command_line(config));
+            process
+        }
+        None => {
+            let mut process = Command::new(&config.command);
+            process.args(&config.args);
+            process
+        }
+    };
+    configure_process(&mut process, config);
+    if let Some(output) = output {
+        match (File::create(&output.stdout), File::create(&output.stderr)) {
+            (Ok(stdout), Ok(stderr)) => {
+                process.stdout(stdout).stderr(stderr);
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                let msg = format!("could not create the output files of the run: {}", e);
+                return Report::new(name.to_string(), Duration::from_secs(0),
+                                   Outcome::SpawnError(msg));
+            }
+        }
+    }
+    if config.timeout.is_some() {
+        // own process group, so a timeout can kill everything the command started
+        process.process_group(0);
+    }
+    if let Some(cpus) = cpus {
+        let set = affinity::cpu_set(cpus);
+        // the child only does a system call before exec
+        unsafe {
+            process.pre_exec(move || affinity::set_affinity(&set));
+        }
+    }
+
+    let start_time = Instant::now();
+    let child = match process.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            let msg = format!("{} (command: {}, workdir: {})", e, config.command,
+                              config.directory);
+            return Report::new(name.to_string(), start_time.elapsed(), Outcome::SpawnError(msg));
+        }
+    };
+
+    let watchdog = config.timeout.map(|timeout| {
+        start_watchdog(child.id(), timeout, config.timeout_grace)
+    });
+    let status = wait_with_usage(child.id());
+    let execution_time = start_time.elapsed();
+
+    let timed_out = match watchdog {
+        Some((done, handle)) => {
+            // the watchdog might already be gone if it killed the process
+            let _ = done.send(());
+            handle.join().unwrap()
+        }
+        None => false,
+    };
+
+    let (outcome, usage) = match status {
+        Ok((_, usage)) if timed_out => (Outcome::TimedOut, Some(usage)),
+        Ok((ecode, usage)) => (Outcome::Exited(ecode), Some(usage)),
+        Err(e) => (Outcome::WaitError(e.to_string()), None),
+    };
+    let mut report = Report::new(name.to_string(), execution_time, outcome);
+    report.usage = usage;
+    report.cpus = cpus.cloned();
+    report
+}
+
+/// Command line for the shell, the arguments are quoted so the shell sees them unchanged.
+fn shell_command_line(config: &RunConfig) -> String {
+    let mut line = config.command.clone();
+    for arg in &config.args {
+        line.push(' ');
+        line.push_str(&quote_shell_word(arg));
+    }
+    line
+}
+
+fn quote_shell_word(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Number of times the empty command line is run to measure the startup of the shell.
+const SHELL_STARTUP_RUNS: usize = 10;
+
+/// Median time the shell takes to start and run an empty command line, in seconds.
+fn measure_shell_startup(shell: &str, config: &RunConfig) -> Result<f64, String> {
+    let mut times = Vec::new();
+    for _ in 0..SHELL_STARTUP_RUNS {
+        let mut process = Command::new(shell);
+        process.arg("-c").arg(":");
+        configure_process(&mut process, config);
+
+        let start = Instant::now();
+        match process.status() {
+            Ok(ecode) if ecode.success() => {
+                times.push(convert_duration_to_seconds(start.elapsed()));
+            }
+            Ok(ecode) => return Err(format!("shell {} failed with {}", shell, ecode)),
+            Err(e) => return Err(format!("shell {} could not start: {}", shell, e)),
+        }
+    }
+    Ok(statistics::median(&times))
+}
+
+/// Reap the child process with `wait4` to get its resource usage together with the exit status.
+fn wait_with_usage(pid: u32) -> io::Result<(ExitStatus, ResourceUsage)> {
+    let mut status: libc::c_int = 0;
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+
+    loop {
+        let ret = unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut usage) };
+        if ret >= 0 {
+            return Ok((ExitStatus::from_raw(status), ResourceUsage::from_rusage(&usage)));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// How often a watchdog checks if the session was interrupted.
+const INTERRUPT_POLL: Duration = Duration::from_millis(100);
+
+/// Spawn a thread that kills the process group `pgid` once `timeout` is over. It first sends
+/// SIGTERM and SIGKILL if the processes are still alive after `grace`. Ctrl-C only reaches the
+/// process group of macrobm, so an interrupt is passed on with SIGINT, again followed by SIGKILL
+/// after `grace`.
+/// Sending on the returned channel stops the watchdog, joining it tells if it had to kill
+/// because of the timeout.
+fn start_watchdog(pgid: u32, timeout: Duration, grace: Duration)
+    -> (Sender<()>, JoinHandle<bool>) {
+    let (done_tx, done_rx) = channel();
+
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        let signal = loop {
+            if interrupted() {
+                break libc::SIGINT;
+            }
+            let left = timeout.checked_sub(start.elapsed()).unwrap_or_default();
+            if left == Duration::from_secs(0) {
+                break libc::SIGTERM;
+            }
+            if done_rx.recv_timeout(left.min(INTERRUPT_POLL)) != Err(RecvTimeoutError::Timeout) {
+                return false;
+            }
+        };
+        kill_process_group(pgid, signal);
+
+        if done_rx.recv_timeout(grace) == Err(RecvTimeoutError::Timeout) {
+            kill_process_group(pgid, libc::SIGKILL);
+        }
+        signal == libc::SIGTERM
+    });
+    (done_tx, handle)
+}
+
+fn kill_process_group(pgid: u32, signal: libc::c_int) {
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), signal);
     }
 }
 
@@ -100,3 +917,436 @@ fn test_duration_conversion_to_seconds_float() {
     let d = Duration::from_millis(1500);
     assert_eq!(convert_duration_to_seconds(d), 1.5);
 }
+
+#[allow(dead_code)]
+fn test_run_config(command: &str) -> RunConfig {
+    RunConfig {
+        name: command.to_string(),
+        description: String::new(),
+        count: 1,
+        warmup: 0,
+        min_runs: 5,
+        max_runs: 100,
+        target_rel_ci: None,
+        time_budget: None,
+        command: command.to_string(),
+        args: Vec::new(),
+        shell: None,
+        subtract_shell_startup: false,
+        directory: ".".to_string(),
+        environment: Vec::new(),
+        configured_environment: Vec::new(),
+        clear_environment: false,
+        unset_environment: Vec::new(),
+        cpus: None,
+        capture: Capture::None,
+        log_directory: "macrobm_logs".to_string(),
+        expect_stdout: None,
+        expect_stderr: None,
+        expect_exit_code: None,
+        timeout: None,
+        timeout_grace: Duration::from_secs(5),
+        setup: None,
+        teardown: None,
+        prepare: None,
+        cleanup: None,
+        parameters: BTreeMap::new(),
+        requirements: Vec::new(),
+    }
+}
+
+#[allow(dead_code)]
+fn test_cases(name: &str, config: RunConfig) -> BTreeMap<String, RunConfig> {
+    let mut cases = BTreeMap::new();
+    cases.insert(name.to_string(), config);
+    cases
+}
+
+#[test]
+fn test_spawn_error_produces_report() {
+    let config = test_run_config("/nonexistent/macrobm_command");
+    let report = run_once("missing", &config, None, None);
+
+    assert_eq!(report.name, "missing");
+    match report.outcome {
+        Outcome::SpawnError(ref msg) => assert!(msg.contains("/nonexistent/macrobm_command")),
+        _ => panic!("Expected a spawn error!"),
+    }
+}
+
+#[test]
+fn test_warmup_runs_are_marked() {
+    let mut config = test_run_config("true");
+    config.count = 2;
+    config.warmup = 3;
+
+    let pool = ThreadPool::new(2);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("warm", config), &BTreeMap::new(), RunOrder::Sequential,
+                  None, tx);
+
+    let reports: Vec<Report> = rx.iter().take(5).collect();
+    // warmups are finished before any measured run is started
+    assert!(reports[..3].iter().all(|r| r.warmup));
+    assert!(reports[3..].iter().all(|r| !r.warmup));
+}
+
+#[test]
+fn test_hooks_around_runs() {
+    use std::fs;
+    let dir = std::env::temp_dir().join("macrobm_test_hooks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut config = test_run_config("test");
+    config.directory = dir.to_str().unwrap().to_string();
+    config.count = 3;
+    // the measured command fails if prepare did not run or cleanup of the last run did not
+    config.args = vec!["-f".to_string(), "prepared".to_string()];
+    config.setup = Some("touch setup".to_string());
+    config.prepare = Some("test -f setup && test ! -f prepared && touch prepared".to_string());
+    config.cleanup = Some("rm prepared && echo x >> runs".to_string());
+    config.teardown = Some("rm setup".to_string());
+
+    let pool = ThreadPool::new(1);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("hooks", config), &BTreeMap::new(), RunOrder::Sequential,
+                  None, tx);
+
+    for report in rx.iter().take(3) {
+        match report.outcome {
+            Outcome::Exited(ecode) => assert!(ecode.success()),
+            _ => panic!("Unexpected outcome {:?}", report.outcome),
+        }
+    }
+    assert_eq!(fs::read_to_string(dir.join("runs")).unwrap(), "x\nx\nx\n");
+    assert!(!dir.join("setup").exists());
+}
+
+#[test]
+fn test_failing_setup_reports_every_run() {
+    let mut config = test_run_config("true");
+    config.count = 2;
+    config.warmup = 1;
+    config.setup = Some("false".to_string());
+
+    let pool = ThreadPool::new(1);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("broken", config), &BTreeMap::new(), RunOrder::Sequential,
+                  None, tx);
+
+    let reports: Vec<Report> = rx.iter().collect();
+    assert_eq!(reports.len(), 3);
+    assert_eq!(reports.iter().filter(|report| report.warmup).count(), 1);
+    assert_eq!(reports.iter().filter(|report| !report.warmup).count(), 2);
+    for report in reports {
+        match report.outcome {
+            Outcome::HookError(ref msg) => assert!(msg.starts_with("setup")),
+            _ => panic!("Expected a hook error!"),
+        }
+    }
+}
+
+#[test]
+fn test_failing_cleanup_keeps_measurement() {
+    let mut config = test_run_config("true");
+    config.count = 2;
+    config.cleanup = Some("false".to_string());
+    config.teardown = Some("false".to_string());
+
+    let pool = ThreadPool::new(1);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("unclean", config), &BTreeMap::new(), RunOrder::Sequential,
+                  None, tx);
+
+    let reports: Vec<Report> = rx.iter().collect();
+    assert_eq!(reports.len(), 2);
+    for report in &reports {
+        match report.outcome {
+            Outcome::Exited(ecode) => assert!(ecode.success()),
+            _ => panic!("Unexpected outcome {:?}", report.outcome),
+        }
+        assert!(report.hook_errors[0].starts_with("cleanup"));
+    }
+    assert_eq!(reports[0].hook_errors.len(), 1);
+    assert!(reports[1].hook_errors[1].starts_with("teardown"));
+}
+
+#[test]
+fn test_session_tears_down_once() {
+    use std::fs;
+    let dir = std::env::temp_dir().join(format!("macrobm_test_teardown_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut config = test_run_config("true");
+    config.directory = dir.to_str().unwrap().to_string();
+    config.count = 2;
+    config.teardown = Some("echo x >> torn_down".to_string());
+
+    let pool = ThreadPool::new(2);
+    let (tx, rx) = channel();
+    let session = do_benchmarks(&pool, &test_cases("once", config), &BTreeMap::new(),
+                                RunOrder::Sequential, None, tx);
+    assert_eq!(rx.iter().count(), 2);
+    assert!(session.stop().is_empty());
+
+    let torn_down = fs::read_to_string(dir.join("torn_down")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(torn_down, "x\n");
+}
+
+#[test]
+fn test_resource_usage_is_recorded() {
+    let mut config = test_run_config("sh");
+    // burn some cpu time in user mode
+    config.args = vec!["-c".to_string(), "i=0; while [ $i -lt 20000 ]; do i=$((i+1)); done"
+                       .to_string()];
+    let report = run_once("busy", &config, None, None);
+    let usage = report.usage.unwrap();
+
+    assert!(usage.user_time + usage.sys_time > 0.);
+    assert!(usage.max_rss > 0);
+    assert!(usage.minor_faults > 0);
+}
+
+#[test]
+fn test_resource_usage_metric_names() {
+    let usage = ResourceUsage { max_rss: 42, ..ResourceUsage::default() };
+    let metrics = usage.metrics();
+
+    assert_eq!(metrics.len(), USAGE_METRICS.len());
+    assert_eq!(metrics[2], ("max_rss", 42., "kB"));
+}
+
+#[test]
+fn test_next_batch() {
+    let mut config = test_run_config("true");
+    config.min_runs = 3;
+    config.max_runs = 10;
+    config.target_rel_ci = Some(1.);
+    let stable = [1., 1., 1.];
+    let noisy = [1., 2., 3.];
+    let second = Duration::from_secs(1);
+
+    assert_eq!(next_batch(&config, &stable, 3, second, 4).unwrap_err().reason,
+               StopReason::TargetReached);
+    assert_eq!(next_batch(&config, &noisy, 3, second, 4), Ok(4));
+    assert_eq!(next_batch(&config, &noisy, 8, second, 4), Ok(2), "Bounded by max_runs");
+    assert_eq!(next_batch(&config, &noisy, 10, second, 4).unwrap_err().reason,
+               StopReason::MaxRuns);
+    // min_runs are done, even if the target is reached earlier
+    assert_eq!(next_batch(&config, &stable[..2], 2, second, 4), Ok(1));
+
+    config.time_budget = Some(second);
+    let stop = next_batch(&config, &noisy, 3, second, 4).unwrap_err();
+    assert_eq!(stop.reason, StopReason::TimeBudget);
+    assert_eq!(stop.runs, 3);
+    // the batch is limited by the runs that fit into the budget
+    assert_eq!(next_batch(&config, &noisy, 3, Duration::from_millis(600), 4), Ok(2));
+}
+
+#[test]
+fn test_next_batch_time_budget() {
+    let mut config = test_run_config("true");
+    config.min_runs = 5;
+    config.max_runs = 100;
+    config.time_budget = Some(Duration::from_secs(10));
+    assert!(config.is_adaptive());
+    let samples = [0.2; 5];
+
+    // 5 runs took 1s, 45 more fit into the remaining 9s
+    assert_eq!(next_batch(&config, &samples, 5, Duration::from_secs(1), 2), Ok(45));
+    config.max_runs = 20;
+    assert_eq!(next_batch(&config, &samples, 5, Duration::from_secs(1), 2), Ok(15));
+    assert_eq!(next_batch(&config, &samples, 5, Duration::from_millis(9900), 2)
+               .unwrap_err().reason, StopReason::TimeBudget);
+    // min_runs are done even if the budget is too small
+    assert_eq!(next_batch(&config, &samples[..3], 3, Duration::from_secs(20), 2), Ok(2));
+}
+
+#[test]
+fn test_adaptive_case_stops() {
+    let mut config = test_run_config("true");
+    config.min_runs = 3;
+    config.max_runs = 7;
+    // unreachable target, the case stops after max_runs
+    config.target_rel_ci = Some(0.);
+
+    let pool = ThreadPool::new(2);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("adaptive", config), &BTreeMap::new(), RunOrder::Sequential,
+                  None, tx);
+
+    let reports: Vec<Report> = rx.iter().collect();
+    assert_eq!(reports.len(), 7);
+    let stops: Vec<&Stop> = reports.iter().filter_map(|r| r.stop.as_ref()).collect();
+    assert_eq!(stops.len(), 1);
+    assert_eq!(stops[0].reason, StopReason::MaxRuns);
+    assert_eq!(stops[0].runs, 7);
+}
+
+#[test]
+fn test_adaptive_case_continues() {
+    let mut config = test_run_config("true");
+    config.min_runs = 3;
+    config.max_runs = 7;
+    config.target_rel_ci = Some(0.);
+    let mut done = BTreeMap::new();
+    done.insert("resumed".to_string(), AdaptiveProgress { samples: vec![1., 2., 3., 4., 5.],
+                                                          runs: 5,
+                                                          spent: Duration::from_secs(15) });
+
+    let pool = ThreadPool::new(2);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("resumed", config.clone()), &done, RunOrder::Sequential,
+                  None, tx);
+    let reports: Vec<Report> = rx.iter().collect();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[1].stop.as_ref().unwrap().runs, 7);
+
+    // the time budget was already used up by the earlier session
+    config.time_budget = Some(Duration::from_secs(10));
+    config.target_rel_ci = None;
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("resumed", config), &done, RunOrder::Sequential, None, tx);
+    assert_eq!(rx.iter().count(), 0);
+}
+
+#[test]
+fn test_run_order() {
+    assert_eq!(run_order(&[2, 3, 1], RunOrder::Sequential), vec![0, 0, 1, 1, 1, 2]);
+    assert_eq!(run_order(&[2, 3, 1], RunOrder::RoundRobin), vec![0, 1, 2, 0, 1, 1]);
+
+    let shuffled = run_order(&[2, 3, 1], RunOrder::Shuffled(42));
+    assert_eq!(shuffled, run_order(&[2, 3, 1], RunOrder::Shuffled(42)), "Same seed, same order");
+    let mut sorted = shuffled.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec![0, 0, 1, 1, 1, 2]);
+    assert!((0..10).map(|seed| run_order(&[2, 3, 1], RunOrder::Shuffled(seed)))
+            .any(|order| order != shuffled));
+}
+
+#[test]
+fn test_round_robin_runs() {
+    let mut first = test_run_config("true");
+    first.count = 3;
+    first.warmup = 1;
+    let mut second = test_run_config("true");
+    second.count = 3;
+    let mut cases = test_cases("a", first);
+    cases.insert("b".to_string(), second);
+
+    let pool = ThreadPool::new(1);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &cases, &BTreeMap::new(), RunOrder::RoundRobin, None, tx);
+
+    let reports: Vec<(String, bool)> = rx.iter().map(|r| (r.name, r.warmup)).collect();
+    let expected: Vec<(String, bool)> = [("a", true), ("a", false), ("b", false), ("a", false),
+                                         ("b", false), ("a", false), ("b", false)]
+        .iter().map(|&(name, warmup)| (name.to_string(), warmup)).collect();
+    assert_eq!(reports, expected);
+}
+
+#[test]
+fn test_pinned_runs() {
+    use std::fs;
+    let cpu = affinity::available_cpus()[0];
+    let file = std::env::temp_dir().join("macrobm_test_pinned");
+
+    let mut config = test_run_config("sh");
+    config.args = vec!["-c".to_string(),
+                       format!("grep Cpus_allowed_list /proc/self/status > {}", file.display())];
+    config.cpus = Some(vec![cpu]);
+    let report = run_iteration("pinned", "run_1", &config, None);
+    assert_eq!(report.cpus, Some(vec![cpu]));
+    assert!(fs::read_to_string(&file).unwrap().trim().ends_with(&format!("\t{}", cpu)));
+
+    // with exclusive cores the run gets a set of the workers
+    config.cpus = None;
+    let slots = Arc::new(CpuSlots::split(&[cpu], 1).unwrap());
+    let report = run_iteration("exclusive", "run_1", &config, Some(&slots));
+    assert_eq!(report.cpus, Some(vec![cpu]));
+    assert_eq!(slots.take(), vec![cpu], "The set is given back");
+}
+
+#[test]
+fn test_shell_command_line() {
+    let mut config = test_run_config("echo $HOME |");
+    config.args = vec!["-f".to_string(), "my file".to_string(), "it's".to_string(), String::new()];
+    assert_eq!(shell_command_line(&config), "echo $HOME | -f 'my file' 'it'\\''s' ''");
+}
+
+#[test]
+fn test_shell_runs() {
+    let mut config = test_run_config("test \"$0\" = sh -a");
+    config.shell = Some("sh".to_string());
+    config.args = vec!["a b".to_string(), "=".to_string(), "a b".to_string()];
+    match run_once("shell", &config, None, None).outcome {
+        Outcome::Exited(ecode) => assert!(ecode.success()),
+        outcome => panic!("Unexpected outcome {:?}", outcome),
+    }
+
+    config.subtract_shell_startup = true;
+    let pool = ThreadPool::new(1);
+    let (tx, rx) = channel();
+    do_benchmarks(&pool, &test_cases("shell", config), &BTreeMap::new(), RunOrder::Sequential,
+                  None, tx);
+    let report = rx.recv().unwrap();
+    assert!(report.shell_startup.unwrap() > 0.);
+    assert!(report.duration >= 0.);
+}
+
+#[test]
+fn test_capture_output() {
+    let dir = std::env::temp_dir().join("macrobm_test_capture");
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut config = test_run_config("sh");
+    config.args = vec!["-c".to_string(), "echo out; echo err >&2; exit $1".to_string(),
+                       "sh".to_string(), "0".to_string()];
+    config.capture = Capture::Failures;
+    config.log_directory = dir.to_str().unwrap().to_string();
+
+    // successful runs do not keep their output
+    run_iteration("case/1", "run_1", &config, None);
+    assert!(!dir.join("case_1").join("run_1.stdout").exists());
+
+    config.args[3] = "1".to_string();
+    run_iteration("case/1", "run_2", &config, None);
+    let log = dir.join("case_1");
+    assert_eq!(fs::read_to_string(log.join("run_2.stdout")).unwrap(), "out\n");
+    assert_eq!(fs::read_to_string(log.join("run_2.stderr")).unwrap(), "err\n");
+
+    config.capture = Capture::All;
+    config.args[3] = "0".to_string();
+    run_iteration("case/1", "warmup_1", &config, None);
+    assert!(log.join("warmup_1.stdout").exists());
+}
+
+#[test]
+fn test_expectations() {
+    let mut config = test_run_config("sh");
+    config.args = vec!["-c".to_string(), "echo 'error: no input'; exit 3".to_string()];
+    let invalid = |config: &RunConfig| match run_iteration("check", "run_1", config, None).outcome {
+        Outcome::Invalid(_, msg) => Some(msg),
+        Outcome::Exited(_) | Outcome::Failed(_) => None,
+        outcome => panic!("Unexpected outcome {:?}", outcome),
+    };
+
+    assert_eq!(invalid(&config), None);
+    // without expected exit codes only 0 is a success
+    let outcome = run_iteration("check", "run_1", &config, None).outcome;
+    assert!(matches!(outcome, Outcome::Failed(ecode) if ecode.code() == Some(3)));
+    config.expect_exit_code = Some(vec![0]);
+    assert!(invalid(&config).unwrap().contains("instead of exit code [0]"));
+    config.expect_exit_code = Some(vec![0, 3]);
+    assert_eq!(invalid(&config), None);
+    let outcome = run_iteration("check", "run_1", &config, None).outcome;
+    assert!(matches!(outcome, Outcome::Exited(_)));
+
+    config.expect_stdout = Some(Regex::new("^result").unwrap());
+    assert_eq!(invalid(&config).unwrap(), "stdout does not match `^result`");
+    config.expect_stdout = Some(Regex::new("^error").unwrap());
+    config.expect_stderr = Some(Regex::new(".").unwrap());
+    assert_eq!(invalid(&config).unwrap(), "stderr does not match `.`");
+}
@@ -1,11 +1,18 @@
-/// We parse configs cause we are so nice
+//! We parse configs cause we are so nice
 
 use yaml_rust::{Yaml, YamlLoader};
 
-use benchmarking::RunConfig;
+use benchmarking::{Capture, RunConfig, RunOrder};
+use regex::Regex;
+use random;
+use affinity;
+use requirements::Requirement;
 use std::fs::File;
 use std::io::Read;
 use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::time::Duration;
 use messages;
 
 /// Read in a file and try to generate yml out of it. Will panic if yaml cant be loaded.
@@ -33,42 +40,138 @@ pub fn file_to_yaml(file_name: &str) -> Vec<Yaml> {
 }
 
 
+/// Everything that is configured for a benchmark session.
+#[derive(Debug)]
+pub struct SessionConfig {
+    pub cases: BTreeMap<String, RunConfig>,
+    pub order: RunOrder, // order the runs of all cases are started in
+}
+
 /// Parse the config file and create internal data structure used to spawn a benchmark.
-pub fn parse_config_file(file_name: &str) -> BTreeMap<String, RunConfig> {
+pub fn parse_config_file(file_name: &str) -> SessionConfig {
     let yaml_doc = file_to_yaml(file_name);
     let doc = &yaml_doc[0];
-    config_from_yaml(doc)
+    SessionConfig {
+        cases: config_from_yaml(doc),
+        order: order_from_yaml(doc),
+    }
+}
+
+/// Requirements `macrobm check` applies to a result file.
+#[derive(Debug)]
+pub struct RequirementsConfig {
+    pub global: Vec<Requirement>, // for every case of the result file that is not listed
+    pub cases: BTreeMap<String, Vec<Requirement>>, // listed cases, with the global ones merged
+}
+
+/// Parse the requirements from a file. This can be the benchmark configuration itself or a file
+/// with the same layout, where cases only need a `name` and `requirements` and may be left out
+/// completely. Baseline files are relative to the directory of the file.
+pub fn parse_requirements_file(file_name: &str) -> RequirementsConfig {
+    let yaml_doc = file_to_yaml(file_name);
+    let doc = &yaml_doc[0];
+    let directory = Path::new(file_name).parent().unwrap_or_else(|| Path::new(""));
+    let resolve = |requirements: Vec<Requirement>| -> Vec<Requirement> {
+        requirements.into_iter()
+            .map(|requirement| match requirement {
+                Requirement::MaxRegressionVs(file, tolerance) => {
+                    let file = directory.join(file).to_string_lossy().into_owned();
+                    Requirement::MaxRegressionVs(file, tolerance)
+                }
+                requirement => requirement,
+            })
+            .collect()
+    };
+
+    RequirementsConfig {
+        global: resolve(yaml_to_requirements(&doc["requirements"])),
+        cases: cases_from_yaml(doc, false).into_iter()
+            .map(|(name, cfg)| (name, resolve(cfg.requirements)))
+            .collect(),
+    }
 }
 
 /// Parse a yml document as configuration.
 fn config_from_yaml(doc: &Yaml) -> BTreeMap<String, RunConfig> {
+    cases_from_yaml(doc, true)
+}
+
+/// Build the configuration of all cases. A command is only necessary to run the benchmarks.
+fn cases_from_yaml(doc: &Yaml, require_command: bool) -> BTreeMap<String, RunConfig> {
     let mut cfg = BTreeMap::<String, RunConfig>::new();
 
     // default values, that can be set global for all cases
     let default_cmd = doc["command"].as_str().unwrap_or("");
     let default_count = doc["count"].as_i64().unwrap_or(1);
+    let default_warmup = doc["warmup"].as_i64().unwrap_or(0);
+    let default_min_runs = doc["min_runs"].as_i64().unwrap_or(5);
+    let default_max_runs = doc["max_runs"].as_i64().unwrap_or(100);
+    let default_target_ci = yaml_to_optional_percent(&doc["target_rel_ci"]);
+    let default_budget = yaml_to_duration(&doc["time_budget"]);
     let default_dir = doc["directory"].as_str().unwrap_or(".");
-    let default_args = match doc["args"].as_vec() {
-        Some(v) => yaml_args_to_stringlist(v),
-        None => Vec::<String>::new(),
-    };
+    let default_args = yaml_to_args(&doc["args"]).unwrap_or_default();
+    let default_shell = yaml_to_shell(&doc["shell"]);
+    let default_subtract = doc["subtract_shell_startup"].as_bool().unwrap_or(false);
     let default_env = match doc["environment"].as_vec() {
+        Some(v) => parse_environment(&yaml_stringarray_to_native(v)),
+        None => Vec::new(),
+    };
+    let default_clear_env = doc["clear_environment"].as_bool().unwrap_or(false);
+    let default_unset_env = match doc["unset_environment"].as_vec() {
         Some(v) => yaml_stringarray_to_native(v),
-        None => vec!["".to_string()],
+        None => Vec::new(),
     };
+    let default_cpus = yaml_to_cpus(&doc["cpus"]);
+    let default_capture = yaml_to_capture(&doc["capture"]).unwrap_or(Capture::None);
+    let default_log_dir = doc["log_directory"].as_str().unwrap_or("macrobm_logs");
+    let default_expect_stdout = yaml_to_regex(&doc["expect_stdout"]);
+    let default_expect_stderr = yaml_to_regex(&doc["expect_stderr"]);
+    let default_exit_codes = yaml_to_exit_codes(&doc["expect_exit_code"]);
+    let default_setup = doc["setup"].as_str();
+    let default_teardown = doc["teardown"].as_str();
+    let default_prepare = doc["prepare"].as_str();
+    let default_cleanup = doc["cleanup"].as_str();
+    let default_timeout = yaml_to_duration(&doc["timeout"]);
+    let default_grace = yaml_to_duration(&doc["timeout_grace"])
+        .unwrap_or(Duration::from_secs(5));
+    let default_matrix = &doc["matrix"];
+    let default_requirements = yaml_to_requirements(&doc["requirements"]);
 
 
-    for bm in doc["cases"].as_vec().unwrap() {
+    let cases = match doc["cases"].as_vec() {
+        Some(cases) => cases.as_slice(),
+        // requirements files may only have global requirements
+        None if !require_command => &[],
+        None => panic!("No cases configured!"),
+    };
+    for bm in cases {
         let cmd = bm["command"].as_str().unwrap_or(default_cmd).to_string();
 
-        if cmd.is_empty() {
+        if cmd.is_empty() && (require_command || bm["name"].is_badvalue()) {
             panic!("No command provided for this benchmark!")
         }
 
         let key = bm["name"].as_str().unwrap_or(&cmd).to_string();
-        let args = match bm["args"].as_vec() {
-            Some(v) => yaml_args_to_stringlist(v),
-            None => default_args.clone(),
+        // requirements of the case replace the default requirement of the same kind
+        let mut requirements = default_requirements.clone();
+        for requirement in yaml_to_requirements(&bm["requirements"]) {
+            requirements.retain(|r| r.key() != requirement.key());
+            requirements.push(requirement);
+        }
+        let args = yaml_to_args(&bm["args"]).unwrap_or_else(|| default_args.clone());
+        let shell = match bm["shell"] {
+            Yaml::BadValue => default_shell.clone(),
+            ref shell => yaml_to_shell(shell),
+        };
+        let subtract_shell_startup = bm["subtract_shell_startup"].as_bool()
+            .unwrap_or(default_subtract);
+        if subtract_shell_startup && shell.is_none() {
+            panic!("subtract_shell_startup of {} needs a shell!", key);
+        }
+
+        let configured_environment = match bm["environment"].as_vec() {
+            Some(v) => parse_environment(&yaml_stringarray_to_native(v)),
+            None => default_env.clone(),
         };
 
         // fill configuration with values and/or default values
@@ -76,22 +179,503 @@ fn config_from_yaml(doc: &Yaml) -> BTreeMap<String, RunConfig> {
             name: key.clone(),
             description: bm["description"].as_str().unwrap_or("").to_string(),
             count: bm["count"].as_i64().unwrap_or(default_count),
+            warmup: bm["warmup"].as_i64().unwrap_or(default_warmup),
+            min_runs: bm["min_runs"].as_i64().unwrap_or(default_min_runs),
+            max_runs: bm["max_runs"].as_i64().unwrap_or(default_max_runs),
+            // `~` disables a global target or budget for a case
+            target_rel_ci: match bm["target_rel_ci"] {
+                Yaml::Null => None,
+                ref target => yaml_to_optional_percent(target).or(default_target_ci),
+            },
+            time_budget: match bm["time_budget"] {
+                Yaml::Null => None,
+                ref budget => yaml_to_duration(budget).or(default_budget),
+            },
 
             command: cmd,
-            args: args,
+            args,
+            shell,
+            subtract_shell_startup,
             directory: bm["directory"].as_str().unwrap_or(default_dir).to_string(),
-            environment: match bm["environment"].as_vec() {
+            environment: expand_environment(&configured_environment),
+            configured_environment,
+            clear_environment: bm["clear_environment"].as_bool().unwrap_or(default_clear_env),
+            unset_environment: match bm["unset_environment"].as_vec() {
                 Some(v) => yaml_stringarray_to_native(v),
-                None => default_env.clone(),
+                None => default_unset_env.clone(),
             },
+            cpus: yaml_to_cpus(&bm["cpus"]).or_else(|| default_cpus.clone()),
+
+            capture: yaml_to_capture(&bm["capture"]).unwrap_or(default_capture),
+            log_directory: bm["log_directory"].as_str().unwrap_or(default_log_dir).to_string(),
+            expect_stdout: yaml_to_regex(&bm["expect_stdout"])
+                .or_else(|| default_expect_stdout.clone()),
+            expect_stderr: yaml_to_regex(&bm["expect_stderr"])
+                .or_else(|| default_expect_stderr.clone()),
+            expect_exit_code: yaml_to_exit_codes(&bm["expect_exit_code"])
+                .or_else(|| default_exit_codes.clone()),
+
+            timeout: yaml_to_duration(&bm["timeout"]).or(default_timeout),
+            timeout_grace: yaml_to_duration(&bm["timeout_grace"]).unwrap_or(default_grace),
+
+            setup: bm["setup"].as_str().or(default_setup).map(str::to_string),
+            teardown: bm["teardown"].as_str().or(default_teardown).map(str::to_string),
+            prepare: bm["prepare"].as_str().or(default_prepare).map(str::to_string),
+            cleanup: bm["cleanup"].as_str().or(default_cleanup).map(str::to_string),
+
+            parameters: BTreeMap::new(),
+            requirements,
         };
 
-        cfg.insert(key, cfg_struct);
+        if cfg_struct.is_adaptive() && cfg_struct.min_runs > cfg_struct.max_runs {
+            panic!("min_runs of {} is larger than max_runs!", key);
+        }
+
+        let matrix = if bm["matrix"].is_badvalue() { default_matrix } else { &bm["matrix"] };
+        if matrix.is_badvalue() {
+            insert_case(&mut cfg, cfg_struct);
+            continue;
+        }
+        for parameters in expand_matrix(matrix) {
+            insert_case(&mut cfg, apply_parameters(&cfg_struct, parameters));
+        }
     }
 
     cfg
 }
 
+/// Add a case under its name, two cases with the same name are an error.
+fn insert_case(cfg: &mut BTreeMap<String, RunConfig>, case: RunConfig) {
+    if cfg.contains_key(&case.name) {
+        panic!("Case {} is configured twice!", case.name);
+    }
+    cfg.insert(case.name.clone(), case);
+}
+
+/// Describe a resolved case with the keys of the configuration file, so a result file shows
+/// exactly what was run. Parsing the case again gives the same configuration. The environment
+/// keeps its `${VAR}` references, values of the parent environment like tokens do not belong
+/// into result files.
+pub fn config_to_yaml(config: &RunConfig) -> Yaml {
+    let mut hash = BTreeMap::new();
+    {
+        let mut set = |key: &str, value: Yaml| {
+            hash.insert(Yaml::String(key.to_string()), value);
+        };
+        let string = |value: &str| Yaml::String(value.to_string());
+        let strings = |values: &[String]| Yaml::Array(values.iter().map(|v| string(v)).collect());
+        let real = |value: f64| Yaml::Real(format!("{:?}", value));
+        let seconds = |value: Duration| real(value.as_secs() as f64
+                                             + value.subsec_nanos() as f64 / 1000000000.);
+
+        set("name", string(&config.name));
+        if !config.description.is_empty() {
+            set("description", string(&config.description));
+        }
+        set("count", Yaml::Integer(config.count));
+        set("warmup", Yaml::Integer(config.warmup));
+        set("min_runs", Yaml::Integer(config.min_runs));
+        set("max_runs", Yaml::Integer(config.max_runs));
+        if let Some(target) = config.target_rel_ci {
+            set("target_rel_ci", real(target));
+        }
+        if let Some(budget) = config.time_budget {
+            set("time_budget", seconds(budget));
+        }
+
+        set("command", string(&config.command));
+        set("args", strings(&config.args));
+        if let Some(ref shell) = config.shell {
+            set("shell", string(shell));
+        }
+        set("subtract_shell_startup", Yaml::Boolean(config.subtract_shell_startup));
+        set("directory", string(&config.directory));
+        let environment: Vec<String> = config.configured_environment.iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        set("environment", strings(&environment));
+        set("clear_environment", Yaml::Boolean(config.clear_environment));
+        set("unset_environment", strings(&config.unset_environment));
+        if let Some(ref cpus) = config.cpus {
+            set("cpus", string(&affinity::format_cpu_list(cpus)));
+        }
+
+        set("capture", string(config.capture.name()));
+        set("log_directory", string(&config.log_directory));
+        if let Some(ref regex) = config.expect_stdout {
+            set("expect_stdout", string(regex.as_str()));
+        }
+        if let Some(ref regex) = config.expect_stderr {
+            set("expect_stderr", string(regex.as_str()));
+        }
+        if let Some(ref codes) = config.expect_exit_code {
+            set("expect_exit_code",
+                Yaml::Array(codes.iter().map(|&code| Yaml::Integer(code as i64)).collect()));
+        }
+
+        if let Some(timeout) = config.timeout {
+            set("timeout", seconds(timeout));
+        }
+        set("timeout_grace", seconds(config.timeout_grace));
+
+        let hooks = [("setup", &config.setup), ("teardown", &config.teardown),
+                     ("prepare", &config.prepare), ("cleanup", &config.cleanup)];
+        for &(key, hook) in &hooks {
+            if let Some(ref hook) = *hook {
+                set(key, string(hook));
+            }
+        }
+
+        let mut requirements = BTreeMap::new();
+        for requirement in &config.requirements {
+            let value = match *requirement {
+                Requirement::MaxAvg(limit) | Requirement::MaxMin(limit) |
+                Requirement::MaxMax(limit) | Requirement::MaxRelDev(limit) => real(limit),
+                Requirement::MaxRegressionVs(ref baseline, tolerance) => {
+                    Yaml::String(format!("{} {}%", baseline, tolerance))
+                }
+            };
+            requirements.insert(string(requirement.key()), value);
+        }
+        if !requirements.is_empty() {
+            set("requirements", Yaml::Hash(requirements));
+        }
+    }
+    Yaml::Hash(hash)
+}
+
+/// Parse the order of the runs. A shuffled order without a `seed` gets a new one.
+fn order_from_yaml(doc: &Yaml) -> RunOrder {
+    let name = match doc["order"].as_str() {
+        Some(name) => name,
+        None => return RunOrder::Sequential,
+    };
+    let seed = match doc["seed"].as_i64() {
+        Some(seed) => seed as u64,
+        None => random::seed_from_clock(),
+    };
+    match RunOrder::from_name(name, seed) {
+        Some(order) => order,
+        None => panic!("Unknown order {}, use sequential, round-robin or shuffled!", name),
+    }
+}
+
+/// Parse a list of CPUs like `"2-5"` or a single CPU number.
+fn yaml_to_cpus(value: &Yaml) -> Option<Vec<usize>> {
+    let list = match *value {
+        Yaml::String(ref list) => list.clone(),
+        Yaml::Integer(cpu) => cpu.to_string(),
+        Yaml::BadValue => return None,
+        _ => panic!("Invalid cpus {:?}!", value),
+    };
+    match affinity::parse_cpu_list(&list) {
+        Some(cpus) => Some(cpus),
+        None => panic!("Invalid cpus {}, use a list like 0,2,4-7!", list),
+    }
+}
+
+/// Parse which runs keep their output: `all`, `failures` or `none`.
+fn yaml_to_capture(value: &Yaml) -> Option<Capture> {
+    let name = value.as_str()?;
+    match Capture::from_name(name) {
+        Some(capture) => Some(capture),
+        None => panic!("Unknown capture {}, use all, failures or none!", name),
+    }
+}
+
+fn yaml_to_regex(value: &Yaml) -> Option<Regex> {
+    let pattern = value.as_str()?;
+    match Regex::new(pattern) {
+        Ok(regex) => Some(regex),
+        Err(e) => panic!("Invalid regular expression {}: {}", pattern, e),
+    }
+}
+
+/// Parse a single exit code or a list of them.
+fn yaml_to_exit_codes(value: &Yaml) -> Option<Vec<i32>> {
+    let code = |value: &Yaml| match value.as_i64() {
+        Some(code) => code as i32,
+        None => panic!("Invalid exit code {:?}!", value),
+    };
+    match *value {
+        Yaml::BadValue => None,
+        Yaml::Array(ref codes) => Some(codes.iter().map(code).collect()),
+        ref value => Some(vec![code(value)]),
+    }
+}
+
+/// Parse a hash of requirements like `{ max_avg: 1.2s, max_rel_dev: 3%,
+/// max_regression_vs: baseline.yml 5% }`.
+fn yaml_to_requirements(doc: &Yaml) -> Vec<Requirement> {
+    let hash = match *doc {
+        Yaml::BadValue | Yaml::Null => return Vec::new(),
+        Yaml::Hash(ref h) => h,
+        _ => panic!("Requirements must be a hash, e.g. max_avg: 1.2s!"),
+    };
+    let mut result = Vec::new();
+
+    for (key, value) in hash {
+        let requirement = match key.as_str().unwrap_or("") {
+            "max_avg" => Requirement::MaxAvg(yaml_to_limit(value)),
+            "max_min" => Requirement::MaxMin(yaml_to_limit(value)),
+            "max_max" => Requirement::MaxMax(yaml_to_limit(value)),
+            "max_rel_dev" => Requirement::MaxRelDev(yaml_to_percent(value)),
+            "max_regression_vs" => {
+                let spec = value.as_str()
+                    .expect("max_regression_vs needs a baseline file and a tolerance");
+                let (baseline, tolerance) = split_regression_spec(spec);
+                Requirement::MaxRegressionVs(baseline, tolerance)
+            }
+            k => panic!("Unknown requirement {:?}!", k),
+        };
+        result.push(requirement);
+    }
+    result
+}
+
+/// Split `baseline.yml 5%` into the baseline file and the tolerance after its last space. The
+/// file may contain spaces, without a tolerance the whole value is the file.
+fn split_regression_spec(spec: &str) -> (String, f64) {
+    let spec = spec.trim();
+    if spec.is_empty() {
+        panic!("max_regression_vs needs a baseline file");
+    }
+    if let Some((baseline, tolerance)) = spec.rsplit_once(char::is_whitespace) {
+        if tolerance.trim_end_matches('%').parse::<f64>().is_ok() {
+            return (baseline.trim_end().to_string(), parse_percent(tolerance));
+        }
+    }
+    (spec.to_string(), 0.)
+}
+
+/// Limits are plain numbers in the unit of the metric or durations like `1.2s` for times.
+fn yaml_to_limit(value: &Yaml) -> f64 {
+    match *value {
+        Yaml::Integer(i) => i as f64,
+        Yaml::Real(ref r) => r.parse().unwrap(),
+        Yaml::String(ref s) => match parse_duration(s) {
+            Some(d) => d.as_secs() as f64 + d.subsec_nanos() as f64 / 1000000000.,
+            None => panic!("Invalid limit {:?}!", s),
+        },
+        _ => panic!("Invalid limit {:?}!", value),
+    }
+}
+
+fn yaml_to_percent(value: &Yaml) -> f64 {
+    match *value {
+        Yaml::Integer(i) => i as f64,
+        Yaml::Real(ref r) => r.parse().unwrap(),
+        Yaml::String(ref s) => parse_percent(s),
+        _ => panic!("Invalid percentage {:?}!", value),
+    }
+}
+
+fn yaml_to_optional_percent(value: &Yaml) -> Option<f64> {
+    match *value {
+        Yaml::BadValue | Yaml::Null => None,
+        _ => Some(yaml_to_percent(value)),
+    }
+}
+
+/// Parse `5%` or `5` as five percent.
+fn parse_percent(value: &str) -> f64 {
+    match value.trim().trim_end_matches('%').trim().parse::<f64>() {
+        Ok(p) => p,
+        Err(_) => panic!("Invalid percentage {:?}!", value),
+    }
+}
+
+/// Build every combination of the parameter values in a matrix like
+/// `{ threads: [1, 2], input: [small.ulf, big.ulf] }`.
+fn expand_matrix(matrix: &Yaml) -> Vec<BTreeMap<String, String>> {
+    let matrix = match matrix.as_hash() {
+        Some(m) => m,
+        None => panic!("A matrix must map parameter names to lists of values!"),
+    };
+    let mut combinations = vec![BTreeMap::new()];
+
+    for (param, values) in matrix {
+        let param = match param.as_str() {
+            Some(p) => p.to_string(),
+            None => panic!("Matrix parameter names must be strings!"),
+        };
+        let values = match values.as_vec() {
+            Some(v) => yaml_args_to_stringlist(v),
+            None => panic!("Matrix parameter {} needs a list of values!", param),
+        };
+
+        let mut extended = Vec::new();
+        for combination in &combinations {
+            for value in &values {
+                let mut c: BTreeMap<String, String> = combination.clone();
+                c.insert(param.clone(), value.clone());
+                extended.push(c);
+            }
+        }
+        combinations = extended;
+    }
+    combinations
+}
+
+/// Create the configuration of one matrix combination. `{{param}}` is replaced in the name, the
+/// command and its arguments, the environment, the directory and the hooks. The values of the
+/// parameters the name does not use get appended to keep the names of all combinations
+/// distinct.
+fn apply_parameters(template: &RunConfig, parameters: BTreeMap<String, String>) -> RunConfig {
+    let mut cfg = template.clone();
+    let subst = |s: &str| substitute_parameters(s, &parameters);
+
+    let used = referenced_parameters(&template.name);
+    let values: Vec<String> = parameters.iter()
+        .filter(|&(p, _)| !used.contains(&p.as_str()))
+        .map(|(p, v)| format!("{}={}", p, v))
+        .collect();
+    cfg.name = if values.is_empty() {
+        subst(&template.name)
+    } else {
+        format!("{}[{}]", subst(&template.name), values.join(","))
+    };
+    cfg.command = subst(&template.command);
+    cfg.args = template.args.iter().map(|a| subst(a)).collect();
+    cfg.configured_environment = template.configured_environment.iter()
+        .map(|(k, v)| (subst(k), subst(v)))
+        .collect();
+    cfg.environment = expand_environment(&cfg.configured_environment);
+    cfg.directory = subst(&template.directory);
+    for hook in [&mut cfg.setup, &mut cfg.teardown, &mut cfg.prepare, &mut cfg.cleanup] {
+        *hook = hook.as_ref().map(|h| subst(h));
+    }
+    cfg.parameters = parameters;
+    cfg
+}
+
+/// Names of the parameters `value` references with `{{param}}`.
+fn referenced_parameters(value: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("{{") {
+        let len = match rest[start + 2..].find("}}") {
+            Some(len) => len,
+            None => break,
+        };
+        params.push(rest[start + 2..start + 2 + len].trim());
+        rest = &rest[start + 4 + len..];
+    }
+    params
+}
+
+/// Replace every `{{param}}` in `value`. Referencing an unknown parameter is an error.
+fn substitute_parameters(value: &str, parameters: &BTreeMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("{{") {
+        let len = match rest[start + 2..].find("}}") {
+            Some(len) => len,
+            None => break,
+        };
+        let param = rest[start + 2..start + 2 + len].trim();
+        match parameters.get(param) {
+            Some(v) => {
+                result.push_str(&rest[..start]);
+                result.push_str(v);
+            }
+            None => panic!("Unknown matrix parameter {} in \"{}\"!", param, value),
+        }
+        rest = &rest[start + 4 + len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Arguments are a list or a single string that is split like a shell would do it.
+fn yaml_to_args(value: &Yaml) -> Option<Vec<String>> {
+    match *value {
+        Yaml::Array(ref args) => Some(yaml_args_to_stringlist(args)),
+        Yaml::String(ref args) => match split_shell_words(args) {
+            Some(words) => Some(words),
+            None => panic!("Unterminated quote in the arguments {}!", args),
+        },
+        Yaml::BadValue => None,
+        ref arg => Some(yaml_args_to_stringlist(&vec![arg.clone()])),
+    }
+}
+
+/// `shell: true` runs the command line through `/bin/sh`, a string names the shell to use.
+fn yaml_to_shell(value: &Yaml) -> Option<String> {
+    match *value {
+        Yaml::Boolean(true) => Some("/bin/sh".to_string()),
+        Yaml::String(ref shell) => Some(shell.clone()),
+        Yaml::Boolean(false) | Yaml::Null | Yaml::BadValue => None,
+        _ => panic!("Invalid shell {:?}!", value),
+    }
+}
+
+/// Split a string into words with the quoting rules of a POSIX shell. Returns None if a quote
+/// is not closed.
+fn split_shell_words(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_word {
+                    words.push(word.clone());
+                    word.clear();
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        // a backslash only escapes characters that are special in double quotes
+                        '\\' => match chars.next()? {
+                            '\n' => (),
+                            c @ '$' | c @ '`' | c @ '"' | c @ '\\' => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some('\n') => (),
+                    Some(c) => word.push(c),
+                    None => word.push('\\'),
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
+}
+
 /// Parse a yaml-Vector to strings, to use this list of strings as argument to start the benchmarked
 /// process.
 fn yaml_args_to_stringlist(args: &Vec<Yaml>) -> Vec<String> {
@@ -99,10 +683,10 @@ fn yaml_args_to_stringlist(args: &Vec<Yaml>) -> Vec<String> {
 
     for arg_candidate in args {
         match arg_candidate {
-            &Yaml::Real(ref a) => result.push(a.clone()),
-            &Yaml::Integer(ref a) => result.push(a.to_string()),
-            &Yaml::String(ref a) => result.push(a.clone()),
-            &Yaml::Null => (),
+            Yaml::Real(a) => result.push(a.clone()),
+            Yaml::Integer(a) => result.push(a.to_string()),
+            Yaml::String(a) => result.push(a.clone()),
+            Yaml::Null => (),
             _ => panic!("Wrong argument type passed for that command!"),
         }
     }
@@ -116,13 +700,98 @@ fn yaml_stringarray_to_native(strings: &Vec<Yaml>) -> Vec<String> {
 
     for s in strings {
         match s {
-            &Yaml::String(ref str) => result.push(str.clone()),
+            Yaml::String(str) => result.push(str.clone()),
             _ => panic!("Expected a yaml-String. Provide correct data!"),
         }
     }
     result
 }
 
+/// Split `KEY=VALUE` entries into pairs that can be passed to the benchmarked process.
+/// References like `${HOME}` in the value are replaced with the variable of the parent process.
+fn parse_environment(entries: &[String]) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+
+    for entry in entries {
+        match entry.find('=') {
+            Some(pos) if pos > 0 => {
+                result.push((entry[..pos].to_string(), entry[pos + 1..].to_string()));
+            }
+            _ => panic!("Environment variable must look like KEY=VALUE, got \"{}\"", entry),
+        }
+    }
+    result
+}
+
+/// The environment the command is run with, `${VAR}` in the values is expanded.
+fn expand_environment(environment: &[(String, String)]) -> Vec<(String, String)> {
+    environment.iter()
+        .map(|(key, value)| (key.clone(), expand_variables(value)))
+        .collect()
+}
+
+/// Substitute every `${VAR}` in `value` with the value of `VAR` in the current environment.
+/// Unset variables expand to an empty string, an unterminated `${` is kept as is.
+fn expand_variables(value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        match rest[start + 2..].find('}') {
+            Some(len) => {
+                let var = &rest[start + 2..start + 2 + len];
+                result.push_str(&rest[..start]);
+                result.push_str(&env::var(var).unwrap_or_default());
+                rest = &rest[start + 3 + len..];
+            }
+            None => break,
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Read a duration from yaml. Plain numbers are seconds, strings may carry one of the units
+/// `ms`, `s`, `m` or `h`, e.g. `1.5s` or `10m`. Returns None if the value is not set.
+pub fn yaml_to_duration(value: &Yaml) -> Option<Duration> {
+    let seconds = match *value {
+        Yaml::BadValue | Yaml::Null => return None,
+        Yaml::Integer(i) => i as f64,
+        Yaml::Real(ref r) => r.parse::<f64>().ok()?,
+        Yaml::String(ref s) => match parse_duration(s) {
+            Some(d) => return Some(d),
+            None => panic!("Invalid duration \"{}\"! Use a number with ms, s, m or h.", s),
+        },
+        _ => panic!("Expected a duration, e.g. \"1.5s\" or \"10m\"!"),
+    };
+    if seconds < 0. {
+        panic!("Durations must not be negative!");
+    }
+    Some(Duration::from_millis((seconds * 1000.).round() as u64))
+}
+
+/// Parse a duration string like `250ms`, `1.5s`, `10m` or `2h`. Without unit seconds are assumed.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, factor) = if let Some(n) = value.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = value.strip_suffix('s') {
+        (n, 1.)
+    } else if let Some(n) = value.strip_suffix('m') {
+        (n, 60.)
+    } else if let Some(n) = value.strip_suffix('h') {
+        (n, 3600.)
+    } else {
+        (value, 1.)
+    };
+
+    let seconds = number.trim().parse::<f64>().ok()? * factor;
+    if seconds < 0. {
+        return None;
+    }
+    Some(Duration::from_millis((seconds * 1000.).round() as u64))
+}
+
 
 
 // --------------------- Test for parsing the config files ------------------
@@ -166,6 +835,112 @@ fn test_yaml_strings_to_native_strings_failcondition() {
 }
 
 
+#[test]
+fn test_parse_environment() {
+    env::set_var("MACROBM_TEST_EXPAND", "expanded");
+    let entries = vec!["OMP_NUM_THREADS=4".to_string(),
+                       "EMPTY=".to_string(),
+                       "WITH_EQUALS=a=b".to_string(),
+                       "PATHLIKE=${MACROBM_TEST_EXPAND}/bin:${MACROBM_TEST_UNSET}".to_string()];
+    let configured = parse_environment(&entries);
+    let env = expand_environment(&configured);
+
+    assert_eq!(env.len(), 4);
+    assert_eq!(env[0], ("OMP_NUM_THREADS".to_string(), "4".to_string()));
+    assert_eq!(env[1], ("EMPTY".to_string(), "".to_string()));
+    assert_eq!(env[2], ("WITH_EQUALS".to_string(), "a=b".to_string()));
+    assert_eq!(env[3], ("PATHLIKE".to_string(), "expanded/bin:".to_string()));
+    assert_eq!(configured[3].1, "${MACROBM_TEST_EXPAND}/bin:${MACROBM_TEST_UNSET}");
+}
+
+#[test]
+#[should_panic]
+fn test_parse_environment_failcondition() {
+    parse_environment(&["NO_VALUE".to_string()]);
+}
+
+#[test]
+fn test_expand_variables_unterminated() {
+    assert_eq!(expand_variables("${UNTERMINATED"), "${UNTERMINATED");
+    assert_eq!(expand_variables("plain $HOME"), "plain $HOME");
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+    assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+    assert_eq!(parse_duration("10m"), Some(Duration::from_secs(600)));
+    assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+    assert_eq!(parse_duration("3"), Some(Duration::from_secs(3)));
+    assert_eq!(parse_duration("fast"), None);
+    assert_eq!(parse_duration("-1s"), None);
+}
+
+#[test]
+fn test_yaml_to_duration() {
+    assert_eq!(yaml_to_duration(&Yaml::Integer(2)), Some(Duration::from_secs(2)));
+    assert_eq!(yaml_to_duration(&Yaml::Real("0.5".to_string())), Some(Duration::from_millis(500)));
+    assert_eq!(yaml_to_duration(&Yaml::String("1m".to_string())), Some(Duration::from_secs(60)));
+    assert_eq!(yaml_to_duration(&Yaml::BadValue), None);
+}
+
+#[test]
+#[should_panic]
+fn test_yaml_to_duration_failcondition() {
+    yaml_to_duration(&Yaml::String("forever".to_string()));
+}
+
+#[test]
+fn test_substitute_parameters() {
+    let mut params = BTreeMap::new();
+    params.insert("threads".to_string(), "4".to_string());
+    params.insert("input".to_string(), "big.ulf".to_string());
+
+    assert_eq!(substitute_parameters("-t{{threads}}", &params), "-t4");
+    assert_eq!(substitute_parameters("{{ input }}/{{threads}}", &params), "big.ulf/4");
+    assert_eq!(substitute_parameters("plain {{", &params), "plain {{");
+}
+
+#[test]
+#[should_panic(expected = "Unknown matrix parameter")]
+fn test_substitute_parameters_failcondition() {
+    substitute_parameters("{{typo}}", &BTreeMap::new());
+}
+
+#[test]
+fn test_yaml_to_requirements() {
+    let yaml = YamlLoader::load_from_str("---
+    max_avg: 1.2s
+    max_max: 2
+    max_rel_dev: 3%
+    max_regression_vs: baseline.yml 5%").unwrap();
+    let requirements = yaml_to_requirements(&yaml[0]);
+
+    assert_eq!(requirements.len(), 4);
+    assert!(requirements.contains(&Requirement::MaxAvg(1.2)));
+    assert!(requirements.contains(&Requirement::MaxMax(2.)));
+    assert!(requirements.contains(&Requirement::MaxRelDev(3.)));
+    assert!(requirements.contains(&Requirement::MaxRegressionVs("baseline.yml".to_string(), 5.)));
+}
+
+#[test]
+fn test_regression_baseline_with_spaces() {
+    let yaml = YamlLoader::load_from_str("---
+    max_regression_vs: old results/base line.yml  5%").unwrap();
+    assert_eq!(yaml_to_requirements(&yaml[0]),
+               vec![Requirement::MaxRegressionVs("old results/base line.yml".to_string(), 5.)]);
+
+    assert_eq!(split_regression_spec("base line.yml"), ("base line.yml".to_string(), 0.));
+    assert_eq!(split_regression_spec("baseline.yml 2"), ("baseline.yml".to_string(), 2.));
+}
+
+#[test]
+#[should_panic(expected = "Unknown requirement")]
+fn test_yaml_to_requirements_failcondition() {
+    let yaml = YamlLoader::load_from_str("---
+    fast_enough: true").unwrap();
+    yaml_to_requirements(&yaml[0]);
+}
 
 #[allow(dead_code)]
 fn test_config_helper(yaml_str: &str) -> BTreeMap<String, RunConfig> {
@@ -243,7 +1018,7 @@ fn test_cfg_command_arguments() {
     assert_eq!(cfg["testcase"].count, 15);
     assert_eq!(cfg["testcase"].directory, "/etc/apache2/");
 
-    let expected_args = vec!["string".to_string(), "15".to_string(), "123.14".to_string()];
+    let expected_args = ["string".to_string(), "15".to_string(), "123.14".to_string()];
     assert_eq!(cfg["testcase"].args[0].to_string(), expected_args[0]);
     assert_eq!(cfg["testcase"].args[1].to_string(), expected_args[1]);
     assert_eq!(cfg["testcase"].args[2].to_string(), expected_args[2]);
@@ -282,14 +1057,14 @@ fn test_cfg_realworld1() {
 
     assert_eq!(cfg["hReactor_ct"].command, "../ulf.x");
     assert_eq!(cfg["hReactor_ct"].name, "hReactor_ct");
-    let expected_args = vec!["-f".to_string(), "hReactor/hReactor_ct.ulf".to_string()];
+    let expected_args = ["-f".to_string(), "hReactor/hReactor_ct.ulf".to_string()];
     assert_eq!(cfg["hReactor_ct"].args[0].to_string(), expected_args[0]);
     assert_eq!(cfg["hReactor_ct"].args[1].to_string(), expected_args[1]);
     assert_eq!(cfg["hReactor_ct"].count, 30);
 
     assert_eq!(cfg["hReactor_ct_chem"].command, "../ulf.x");
     assert_eq!(cfg["hReactor_ct_chem"].name, "hReactor_ct_chem");
-    let expected_args = vec!["-f".to_string(), "hReactor/hReactor_ct_chem.ulf".to_string()];
+    let expected_args = ["-f".to_string(), "hReactor/hReactor_ct_chem.ulf".to_string()];
     assert_eq!(cfg["hReactor_ct_chem"].args[0].to_string(),
     expected_args[0]);
     assert_eq!(cfg["hReactor_ct_chem"].args[1].to_string(),
@@ -298,15 +1073,445 @@ fn test_cfg_realworld1() {
 
     assert_eq!(cfg["hReactor_eg"].command, "../ulf.x");
     assert_eq!(cfg["hReactor_eg"].name, "hReactor_eg");
-    let expected_args = vec!["-f".to_string(), "hReactor/hReactor_eg.ulf".to_string()];
+    let expected_args = ["-f".to_string(), "hReactor/hReactor_eg.ulf".to_string()];
     assert_eq!(cfg["hReactor_eg"].args[0].to_string(), expected_args[0]);
     assert_eq!(cfg["hReactor_eg"].args[1].to_string(), expected_args[1]);
     assert_eq!(cfg["hReactor_eg"].count, 30);
 
     assert_eq!(cfg["hReactor_uc"].command, "../ulf.x");
     assert_eq!(cfg["hReactor_uc"].name, "hReactor_uc");
-    let expected_args = vec!["-f".to_string(), "hReactor/hReactor_uc.ulf".to_string()];
+    let expected_args = ["-f".to_string(), "hReactor/hReactor_uc.ulf".to_string()];
     assert_eq!(cfg["hReactor_uc"].args[0].to_string(), expected_args[0]);
     assert_eq!(cfg["hReactor_uc"].args[1].to_string(), expected_args[1]);
     assert_eq!(cfg["hReactor_uc"].count, 30);
 }
+
+#[test]
+fn test_cfg_environment() {
+    let yaml_str = "---
+    environment: [\"OMP_NUM_THREADS=1\"]
+    unset_environment: [\"LANG\"]
+    cases:
+        - name: \"defaulted\"
+          command: \"solver\"
+        - name: \"threaded\"
+          command: \"solver\"
+          environment: [\"OMP_NUM_THREADS=4\", \"BLA=2\"]
+          clear_environment: true";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg["defaulted"].environment,
+               vec![("OMP_NUM_THREADS".to_string(), "1".to_string())]);
+    assert_eq!(cfg["defaulted"].unset_environment, vec!["LANG".to_string()]);
+    assert!(!cfg["defaulted"].clear_environment);
+
+    assert_eq!(cfg["threaded"].environment,
+               vec![("OMP_NUM_THREADS".to_string(), "4".to_string()),
+                    ("BLA".to_string(), "2".to_string())]);
+    assert!(cfg["threaded"].clear_environment);
+}
+
+#[test]
+fn test_cfg_timeout() {
+    let yaml_str = "---
+    timeout: 10m
+    cases:
+        - name: \"defaulted\"
+          command: \"solver\"
+        - name: \"short\"
+          command: \"solver\"
+          timeout: 30
+          timeout_grace: 500ms";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg["defaulted"].timeout, Some(Duration::from_secs(600)));
+    assert_eq!(cfg["defaulted"].timeout_grace, Duration::from_secs(5));
+    assert_eq!(cfg["short"].timeout, Some(Duration::from_secs(30)));
+    assert_eq!(cfg["short"].timeout_grace, Duration::from_millis(500));
+}
+
+#[test]
+fn test_cfg_warmup() {
+    let yaml_str = "---
+    warmup: 2
+    cases:
+        - name: \"defaulted\"
+          command: \"solver\"
+        - name: \"cold\"
+          command: \"solver\"
+          warmup: 0";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg["defaulted"].warmup, 2);
+    assert_eq!(cfg["cold"].warmup, 0);
+}
+
+#[test]
+fn test_cfg_hooks() {
+    let yaml_str = "---
+    prepare: \"rm -rf output && mkdir output\"
+    cases:
+        - name: \"defaulted\"
+          command: \"solver\"
+        - name: \"mesh\"
+          command: \"solver\"
+          setup: \"cp -r mesh.orig mesh\"
+          cleanup: \"cp mesh.orig/input mesh/input\"
+          teardown: \"rm -r mesh\"";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg["defaulted"].prepare, Some("rm -rf output && mkdir output".to_string()));
+    assert_eq!(cfg["defaulted"].setup, None);
+    assert_eq!(cfg["mesh"].prepare, Some("rm -rf output && mkdir output".to_string()));
+    assert_eq!(cfg["mesh"].setup, Some("cp -r mesh.orig mesh".to_string()));
+    assert_eq!(cfg["mesh"].cleanup, Some("cp mesh.orig/input mesh/input".to_string()));
+    assert_eq!(cfg["mesh"].teardown, Some("rm -r mesh".to_string()));
+}
+
+#[test]
+fn test_cfg_matrix() {
+    let yaml_str = "---
+    command: \"../ulf.x\"
+    cases:
+        - name: \"hReactor_{{input}}_t{{threads}}\"
+          args: [\"-f\", \"hReactor/{{input}}.ulf\"]
+          environment: [\"OMP_NUM_THREADS={{threads}}\"]
+          directory: \"run_{{threads}}\"
+          matrix:
+              threads: [1, 4]
+              input: [\"ct\", \"uc\"]
+        - name: \"unnamed\"
+          matrix:
+              threads: [2]";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg.len(), 5);
+    let case = &cfg["hReactor_uc_t4"];
+    assert_eq!(case.args, vec!["-f".to_string(), "hReactor/uc.ulf".to_string()]);
+    assert_eq!(case.environment, vec![("OMP_NUM_THREADS".to_string(), "4".to_string())]);
+    assert_eq!(case.directory, "run_4");
+    assert_eq!(case.parameters["threads"], "4");
+    assert_eq!(case.parameters["input"], "uc");
+    assert!(cfg.contains_key("hReactor_ct_t1"));
+
+    assert_eq!(cfg["unnamed[threads=2]"].parameters["threads"], "2");
+}
+
+#[test]
+fn test_cfg_matrix_partially_named() {
+    let yaml_str = "---
+    cases:
+        - name: \"solve_{{threads}}\"
+          command: \"./solve -t {{threads}} {{input}}\"
+          shell: true
+          setup: \"mkdir -p {{input}}\"
+          cleanup: \"rm -f {{input}}/out\"
+          matrix:
+              threads: [1, 2]
+              input: [small, big]";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg.len(), 4);
+    let case = &cfg["solve_2[input=big]"];
+    assert_eq!(case.command, "./solve -t 2 big");
+    assert_eq!(case.setup, Some("mkdir -p big".to_string()));
+    assert_eq!(case.cleanup, Some("rm -f big/out".to_string()));
+    assert!(cfg.contains_key("solve_1[input=small]"));
+}
+
+#[test]
+#[should_panic]
+fn test_cfg_matrix_failcondition() {
+    test_config_helper("---
+    cases:
+        - name: \"solve_{{threads}}\"
+          command: \"./solve\"
+          matrix:
+              threads: [1, 1]");
+}
+
+#[test]
+fn test_cfg_requirements() {
+    let yaml_str = "---
+    requirements:
+        max_rel_dev: 3%
+        max_avg: 10s
+    cases:
+        - name: \"defaulted\"
+        - name: \"strict\"
+          requirements:
+              max_avg: 1.2s";
+    let yaml = YamlLoader::load_from_str(yaml_str).unwrap();
+    let cfg = cases_from_yaml(&yaml[0], false);
+
+    assert_eq!(cfg["defaulted"].requirements,
+               vec![Requirement::MaxAvg(10.), Requirement::MaxRelDev(3.)]);
+    assert_eq!(cfg["strict"].requirements,
+               vec![Requirement::MaxRelDev(3.), Requirement::MaxAvg(1.2)]);
+}
+
+#[test]
+fn test_requirements_file() {
+    use std::fs;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("macrobm_requirements_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("requirements.yml");
+    fs::write(&file, "requirements:\n    max_regression_vs: baseline.yml 5%\n").unwrap();
+    let requirements = parse_requirements_file(file.to_str().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+
+    let baseline = dir.join("baseline.yml").to_str().unwrap().to_string();
+    assert_eq!(requirements.global, vec![Requirement::MaxRegressionVs(baseline, 5.)]);
+    assert!(requirements.cases.is_empty());
+}
+
+#[test]
+fn test_cfg_adaptive() {
+    let yaml_str = "---
+    command: \"sleep\"
+    target_rel_ci: 1%
+    max_runs: 50
+    cases:
+        - name: \"defaulted\"
+        - name: \"bounded\"
+          min_runs: 10
+          time_budget: 10m
+          target_rel_ci: 0.5";
+    let yaml = YamlLoader::load_from_str(yaml_str).unwrap();
+    let cfg = config_from_yaml(&yaml[0]);
+
+    assert!(cfg["defaulted"].is_adaptive());
+    assert_eq!(cfg["defaulted"].target_rel_ci, Some(1.));
+    assert_eq!(cfg["defaulted"].min_runs, 5);
+    assert_eq!(cfg["defaulted"].planned_runs(), 50);
+    assert_eq!(cfg["bounded"].target_rel_ci, Some(0.5));
+    assert_eq!(cfg["bounded"].min_runs, 10);
+    assert_eq!(cfg["bounded"].time_budget, Some(Duration::from_secs(600)));
+}
+
+#[test]
+fn test_cfg_time_budget() {
+    let yaml_str = "---
+    command: \"sleep\"
+    time_budget: 10m
+    cases:
+        - name: \"budget\"
+        - name: \"fixed\"
+          time_budget: ~";
+    let yaml = YamlLoader::load_from_str(yaml_str).unwrap();
+    let cfg = config_from_yaml(&yaml[0]);
+
+    assert!(cfg["budget"].is_adaptive());
+    assert_eq!(cfg["budget"].target_rel_ci, None);
+    assert_eq!(cfg["budget"].time_budget, Some(Duration::from_secs(600)));
+    assert_eq!(cfg["fixed"].time_budget, None);
+    assert!(!cfg["fixed"].is_adaptive());
+}
+
+#[test]
+#[should_panic(expected = "larger than max_runs")]
+fn test_cfg_adaptive_failcondition() {
+    let yaml_str = "---
+    cases:
+        - command: \"sleep\"
+          target_rel_ci: 1%
+          min_runs: 10
+          max_runs: 5";
+    let yaml = YamlLoader::load_from_str(yaml_str).unwrap();
+    config_from_yaml(&yaml[0]);
+}
+
+#[test]
+fn test_cfg_order() {
+    let order = |yaml_str: &str| order_from_yaml(&YamlLoader::load_from_str(yaml_str).unwrap()[0]);
+
+    assert_eq!(order("cases: []"), RunOrder::Sequential);
+    assert_eq!(order("order: round-robin"), RunOrder::RoundRobin);
+    assert_eq!(order("order: shuffled\nseed: 1234"), RunOrder::Shuffled(1234));
+    match order("order: shuffled") {
+        RunOrder::Shuffled(seed) => assert!(seed < 1 << 63),
+        other => panic!("Unexpected order {:?}", other),
+    }
+}
+
+#[test]
+#[should_panic(expected = "Unknown order")]
+fn test_cfg_order_failcondition() {
+    order_from_yaml(&YamlLoader::load_from_str("order: random").unwrap()[0]);
+}
+
+#[test]
+fn test_cfg_cpus() {
+    let yaml_str = "---
+    command: \"sleep\"
+    cpus: \"2-5\"
+    cases:
+        - name: \"default\"
+        - name: \"pinned\"
+          cpus: 7
+        - name: \"list\"
+          cpus: \"0,2,8-9\"";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg["default"].cpus, Some(vec![2, 3, 4, 5]));
+    assert_eq!(cfg["pinned"].cpus, Some(vec![7]));
+    assert_eq!(cfg["list"].cpus, Some(vec![0, 2, 8, 9]));
+}
+
+#[test]
+#[should_panic(expected = "Invalid cpus")]
+fn test_cfg_cpus_failcondition() {
+    test_config_helper("cases:\n    - command: \"sleep\"\n      cpus: \"5-2\"");
+}
+
+#[test]
+fn test_config_to_yaml() {
+    let yaml_str = "---
+    args: \"-v --input 'big file'\"
+    shell: true
+    subtract_shell_startup: true
+    cases:
+        - name: full
+          command: \"./bench\"
+          description: everything set
+          count: 7
+          warmup: 2
+          target_rel_ci: 2.5%
+          time_budget: 90s
+          directory: /tmp
+          environment:
+            - MODE=fast
+          clear_environment: true
+          unset_environment: [LANG]
+          cpus: 0,2-3
+          capture: failures
+          expect_stdout: \"^ok\"
+          expect_exit_code: [0, 3]
+          timeout: 1.5s
+          setup: make
+          cleanup: rm -f out
+          requirements:
+            max_avg: 1.25s
+            max_regression_vs: base.yml 5%
+        - name: plain
+          command: \"true\"
+          shell: false
+          subtract_shell_startup: false";
+    let cfg = test_config_helper(yaml_str);
+
+    let cases = cfg.values().map(config_to_yaml).collect();
+    let mut doc = BTreeMap::new();
+    doc.insert(Yaml::String("cases".to_string()), Yaml::Array(cases));
+    let parsed = config_from_yaml(&Yaml::Hash(doc));
+
+    assert_eq!(format!("{:?}", parsed), format!("{:?}", cfg));
+    assert_eq!(config_to_yaml(&cfg["full"])["cpus"].as_str(), Some("0,2-3"));
+    assert!(config_to_yaml(&cfg["plain"])["shell"].is_badvalue());
+}
+
+#[test]
+fn test_config_to_yaml_keeps_references() {
+    env::set_var("MACROBM_TEST_SECRET", "hunter2");
+    let cfg = test_config_helper("---
+    cases:
+        - name: \"{{mode}}\"
+          command: \"./bench\"
+          environment: [\"TOKEN=${MACROBM_TEST_SECRET}\", \"MODE={{mode}}\"]
+          matrix:
+              mode: [fast]");
+
+    assert_eq!(cfg["fast"].environment,
+               vec![("TOKEN".to_string(), "hunter2".to_string()),
+                    ("MODE".to_string(), "fast".to_string())]);
+    let environment = config_to_yaml(&cfg["fast"])["environment"].clone();
+    assert_eq!(environment[0].as_str(), Some("TOKEN=${MACROBM_TEST_SECRET}"));
+    assert_eq!(environment[1].as_str(), Some("MODE=fast"));
+}
+
+#[test]
+fn test_split_shell_words() {
+    let words = |line: &str| split_shell_words(line).unwrap();
+
+    assert_eq!(words("-f hReactor.ulf"), vec!["-f", "hReactor.ulf"]);
+    assert_eq!(words("  a\tb  "), vec!["a", "b"]);
+    assert_eq!(words("'single $HOME' \"double \\\"q\\\" \\n\""),
+               vec!["single $HOME", "double \"q\" \\n"]);
+    assert_eq!(words("a\\ b c''d ''"), vec!["a b", "cd", ""]);
+    assert!(words("").is_empty());
+}
+
+#[test]
+fn test_split_shell_words_failcondition() {
+    assert_eq!(split_shell_words("'open"), None);
+    assert_eq!(split_shell_words("\"open"), None);
+}
+
+#[test]
+fn test_cfg_shell() {
+    let yaml_str = "---
+    args: \"-f 'my file.ulf'\"
+    shell: true
+    cases:
+        - name: \"default\"
+          command: \"../ulf.x -f hReactor.ulf\"
+        - name: \"bash\"
+          command: \"echo\"
+          shell: /bin/bash
+          subtract_shell_startup: true
+        - name: \"direct\"
+          command: \"ulf.x\"
+          shell: false
+          args: 1.5";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg["default"].shell, Some("/bin/sh".to_string()));
+    assert_eq!(cfg["default"].args, vec!["-f", "my file.ulf"]);
+    assert!(!cfg["default"].subtract_shell_startup);
+    assert_eq!(cfg["bash"].shell, Some("/bin/bash".to_string()));
+    assert!(cfg["bash"].subtract_shell_startup);
+    assert_eq!(cfg["direct"].shell, None);
+    assert_eq!(cfg["direct"].args, vec!["1.5"]);
+}
+
+#[test]
+#[should_panic(expected = "needs a shell")]
+fn test_cfg_shell_failcondition() {
+    test_config_helper("cases:\n    - command: \"sleep\"\n      subtract_shell_startup: true");
+}
+
+#[test]
+fn test_cfg_output_checks() {
+    let yaml_str = "---
+    command: \"sleep\"
+    capture: failures
+    expect_stdout: \"^done\"
+    cases:
+        - name: \"default\"
+        - name: \"own\"
+          capture: all
+          log_directory: \"logs\"
+          expect_stderr: \"warn(ing)?\"
+          expect_exit_code: [0, 3]
+        - name: \"single\"
+          expect_exit_code: 2";
+    let cfg = test_config_helper(yaml_str);
+
+    assert_eq!(cfg["default"].capture, Capture::Failures);
+    assert_eq!(cfg["default"].log_directory, "macrobm_logs");
+    assert!(cfg["default"].expect_stdout.as_ref().unwrap().is_match("done in 3s"));
+    assert!(cfg["default"].expect_stderr.is_none());
+    assert_eq!(cfg["default"].expect_exit_code, None);
+    assert_eq!(cfg["own"].capture, Capture::All);
+    assert_eq!(cfg["own"].log_directory, "logs");
+    assert!(cfg["own"].expect_stderr.as_ref().unwrap().is_match("a warning"));
+    assert_eq!(cfg["own"].expect_exit_code, Some(vec![0, 3]));
+    assert_eq!(cfg["single"].expect_exit_code, Some(vec![2]));
+}
+
+#[test]
+#[should_panic(expected = "Invalid regular expression")]
+fn test_cfg_output_checks_failcondition() {
+    test_config_helper("cases:\n    - command: \"sleep\"\n      expect_stdout: \"(open\"");
+}
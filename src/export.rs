@@ -0,0 +1,250 @@
+//! Export the raw runs and the statistics as JSON or CSV, for dashboards and notebooks that do
+//! not read the result files.
+
+use yaml_rust::Yaml;
+
+use std::collections::BTreeMap;
+
+use json;
+use messages;
+use metadata::Metadata;
+use results;
+use results::ResultSet;
+use statistics;
+use statistics::BMStatistics;
+
+/// Formats the results can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// All runs with their measurements. JSON has the layout of the result files, so it can be read
+/// back. CSV has a row for every run with the case, its parameters, how the run ended and a
+/// column for every metric.
+pub fn runs(results: &ResultSet, metadata: Option<&Metadata>, format: Format) -> String {
+    match format {
+        Format::Json => json::to_string(&messages::results_to_yaml(results, metadata)),
+        Format::Csv => runs_to_csv(results),
+    }
+}
+
+fn runs_to_csv(results: &ResultSet) -> String {
+    // every case may have other parameters and metrics, the columns are the union of all
+    let mut parameters: Vec<String> = Vec::new();
+    for case in results.values() {
+        for parameter in case.parameters.keys() {
+            if !parameters.contains(parameter) {
+                parameters.push(parameter.clone());
+            }
+        }
+    }
+    let metrics = results::metrics(results);
+
+    let mut header = vec!["case".to_string()];
+    header.extend(parameters.iter().cloned());
+    header.extend(vec!["run".to_string(), "outcome".to_string(), "exit_code".to_string()]);
+    header.extend(metrics.iter().cloned());
+    let mut out = csv_row(&header);
+
+    for (name, case) in results {
+        for (i, run) in case.runs.iter().enumerate() {
+            let mut row = vec![name.clone()];
+            for parameter in &parameters {
+                row.push(case.parameters.get(parameter).cloned().unwrap_or_default());
+            }
+            row.push((i + 1).to_string());
+            row.push(run.status.as_ref().map(|s| s.outcome.name().to_string()).unwrap_or_default());
+            row.push(run.status.as_ref().and_then(|s| s.exit_code)
+                     .map(|code| code.to_string()).unwrap_or_default());
+            for metric in &metrics {
+                row.push(run.value(metric).map(|v| v.to_string()).unwrap_or_default());
+            }
+            out.push_str(&csv_row(&row));
+        }
+    }
+    out
+}
+
+/// The statistics of every case for one `metric`, with one field per selected column. Cases
+/// without a single successful run only have their number of `failed` runs.
+pub fn statistics(stats: &BTreeMap<String, BMStatistics>, failures: &BTreeMap<String, usize>,
+                  metric: &str, confidence: f64, columns: &[&str], format: Format) -> String {
+    let mut names: Vec<&String> = stats.keys().chain(failures.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let cases: Vec<(&String, Fields)> = names.into_iter()
+        .map(|name| {
+            let failed = failures.get(name).cloned().unwrap_or(0);
+            let fields = columns.iter()
+                .flat_map(|column| statistic_fields(column, stats.get(name), failed))
+                .collect();
+            (name, fields)
+        })
+        .collect();
+
+    match format {
+        Format::Json => {
+            let mut yaml_cases = BTreeMap::new();
+            for (name, fields) in cases {
+                let mut case = BTreeMap::new();
+                for (field, value) in fields {
+                    if let Some(value) = value {
+                        case.insert(Yaml::String(field), Yaml::Real(value.to_string()));
+                    }
+                }
+                yaml_cases.insert(Yaml::String(name.clone()), Yaml::Hash(case));
+            }
+            let mut document = BTreeMap::new();
+            document.insert(Yaml::String("metric".to_string()), Yaml::String(metric.to_string()));
+            document.insert(Yaml::String("confidence".to_string()),
+                            Yaml::Real(confidence.to_string()));
+            document.insert(Yaml::String("cases".to_string()), Yaml::Hash(yaml_cases));
+            json::to_string(&Yaml::Hash(document))
+        }
+        Format::Csv => {
+            let mut header = vec!["name".to_string()];
+            header.extend(columns.iter()
+                          .flat_map(|column| statistic_fields(column, None, 0))
+                          .map(|(field, _)| field));
+            let mut out = csv_row(&header);
+            for (name, fields) in cases {
+                let mut row = vec![name.clone()];
+                row.extend(fields.into_iter()
+                           .map(|(_, value)| value.map(|v| v.to_string()).unwrap_or_default()));
+                out.push_str(&csv_row(&row));
+            }
+            out
+        }
+    }
+}
+
+/// Named values of one column of the statistics table, None if a case has no statistics.
+type Fields = Vec<(String, Option<f64>)>;
+
+/// Fields of one column of the statistics table. Intervals and outliers have two values.
+fn statistic_fields(column: &str, stat: Option<&BMStatistics>, failed: usize) -> Fields {
+    let field = |name: &str, value: &dyn Fn(&BMStatistics) -> f64| {
+        (name.to_string(), stat.map(value))
+    };
+    // intervals are only known if the analysis drew resamples
+    let bound = |name: &str, value: &dyn Fn(&BMStatistics) -> Option<f64>| {
+        (name.to_string(), stat.and_then(value))
+    };
+    match column {
+        "runs" => vec![("runs".to_string(), Some(stat.map(|s| s.count).unwrap_or(0) as f64))],
+        "failed" => vec![("failed".to_string(), Some(failed as f64))],
+        "reldev" => vec![field("reldev", &statistics::calc_relative_variance)],
+        "avg_ci" => vec![bound("avg_ci_low", &|s| s.avg_ci.map(|ci| ci.0)),
+                         bound("avg_ci_high", &|s| s.avg_ci.map(|ci| ci.1))],
+        "median_ci" => vec![bound("median_ci_low", &|s| s.median_ci.map(|ci| ci.0)),
+                            bound("median_ci_high", &|s| s.median_ci.map(|ci| ci.1))],
+        "outliers" => vec![field("mild_outliers", &|s| s.mild_outliers as f64),
+                           field("severe_outliers", &|s| s.severe_outliers as f64)],
+        "min" => vec![field("min", &|s| s.min)],
+        "max" => vec![field("max", &|s| s.max)],
+        "avg" => vec![field("avg", &|s| s.avg)],
+        "dev" => vec![field("dev", &|s| s.dev)],
+        "absdev" => vec![field("absdev", &|s| s.absdev)],
+        "median" => vec![field("median", &|s| s.median)],
+        "p5" => vec![field("p5", &|s| s.p5)],
+        "p25" => vec![field("p25", &|s| s.p25)],
+        "p75" => vec![field("p75", &|s| s.p75)],
+        "p95" => vec![field("p95", &|s| s.p95)],
+        "p99" => vec![field("p99", &|s| s.p99)],
+        "iqr" => vec![field("iqr", &|s| s.iqr)],
+        _ => panic!("Unknown column {:?}!", column),
+    }
+}
+
+/// One line of CSV, fields with separators, quotes or line breaks are quoted.
+fn csv_row(fields: &[String]) -> String {
+    let fields: Vec<String> = fields.iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    format!("{}\n", fields.join(","))
+}
+
+
+// --------------------- tests for the export ---------------------------
+
+#[allow(dead_code)]
+fn test_results() -> ResultSet {
+    use results::{CaseResult, RunOutcome, RunRecord, RunStatus};
+
+    let run = |wall: f64, outcome: RunOutcome, exit_code: i32| {
+        let mut record = RunRecord {
+            status: Some(RunStatus { outcome, exit_code: Some(exit_code), signal: None,
+                                     message: None }),
+            ..RunRecord::default()
+        };
+        record.insert("wall", wall, "s");
+        record
+    };
+    let mut results = ResultSet::new();
+    let mut solve = CaseResult::default();
+    solve.parameters.insert("mesh".to_string(), "fine, large".to_string());
+    solve.runs = vec![run(1.5, RunOutcome::Exited, 0), run(2.5, RunOutcome::Failed, 3)];
+    results.insert("solve".to_string(), solve);
+    results
+}
+
+#[test]
+fn test_runs_to_csv() {
+    let csv = runs(&test_results(), None, Format::Csv);
+
+    assert_eq!(csv, "case,mesh,run,outcome,exit_code,wall\n\
+                     solve,\"fine, large\",1,exited,0,1.5\n\
+                     solve,\"fine, large\",2,failed,3,2.5\n");
+}
+
+#[test]
+fn test_runs_to_json() {
+    let exported = runs(&test_results(), None, Format::Json);
+    let doc = json::parse(&exported).unwrap();
+
+    assert_eq!(doc["cases"][0]["solve"]["runs"][1]["outcome"].as_str(), Some("failed"));
+    assert_eq!(doc["cases"][0]["solve"]["runs"][0]["wall"].as_f64(), Some(1.5));
+    assert!(doc["metadata"].is_badvalue());
+}
+
+#[test]
+fn test_statistics_export() {
+    let mut stats = BTreeMap::new();
+    stats.insert("a".to_string(), BMStatistics { avg: 2., count: 3, avg_ci: Some((1.5, 2.5)),
+                                                 ..BMStatistics::default() });
+    let mut failures = BTreeMap::new();
+    failures.insert("a".to_string(), 0);
+    failures.insert("b".to_string(), 2);
+    let columns = ["runs", "failed", "avg", "avg_ci"];
+
+    let csv = statistics(&stats, &failures, "wall", 95., &columns, Format::Csv);
+    assert_eq!(csv, "name,runs,failed,avg,avg_ci_low,avg_ci_high\n\
+                     a,3,0,2,1.5,2.5\n\
+                     b,0,2,,,\n");
+
+    let doc = json::parse(&statistics(&stats, &failures, "wall", 95., &columns,
+                                      Format::Json)).unwrap();
+    assert_eq!(doc["metric"].as_str(), Some("wall"));
+    assert_eq!(doc["cases"]["a"]["avg_ci_high"].as_f64(), Some(2.5));
+    assert_eq!(doc["cases"]["b"]["failed"].as_i64(), Some(2));
+    assert!(doc["cases"]["b"]["avg"].is_badvalue());
+}
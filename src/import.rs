@@ -0,0 +1,222 @@
+//! Import the results of other benchmark tools, so they can be reported and compared like the
+//! results of macrobm. hyperfine's `--export-json` files and the `sample.json` files criterion
+//! writes to `target/criterion` are understood.
+
+use yaml_rust::Yaml;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use json;
+use results::{CaseResult, ResultSet, RunOutcome, RunRecord, RunStatus, WALL_TIME};
+use statistics;
+
+/// Runs of another tool in a file that was already loaded, None if `doc` is a macrobm result
+/// file.
+pub fn from_other_tool(file_name: &str, doc: &Yaml) -> Option<ResultSet> {
+    if is_hyperfine(doc) {
+        Some(from_hyperfine(doc))
+    } else if is_criterion_sample(doc) {
+        let mut results = ResultSet::new();
+        results.insert(criterion_name(Path::new(file_name)), from_criterion_sample(doc));
+        Some(results)
+    } else {
+        None
+    }
+}
+
+/// hyperfine has a list of `results`, each with the `times` of every run.
+fn is_hyperfine(doc: &Yaml) -> bool {
+    doc["results"].as_vec().is_some_and(|results| {
+        results.iter().all(|result| result["times"].as_vec().is_some())
+    })
+}
+
+/// criterion stores the number of `iters` of every sample and the total `times` they took.
+fn is_criterion_sample(doc: &Yaml) -> bool {
+    doc["iters"].as_vec().is_some() && doc["times"].as_vec().is_some()
+}
+
+/// Every command is a case, named by the command or the `--command-name`. The wall time of every
+/// run is known with its exit code, hyperfine only has averages of the other metrics.
+fn from_hyperfine(doc: &Yaml) -> ResultSet {
+    let mut results = ResultSet::new();
+
+    for result in doc["results"].as_vec().unwrap() {
+        let name = result["command"].as_str().expect("hyperfine result without command!");
+        let exit_codes = result["exit_codes"].as_vec();
+        let mut case = CaseResult::default();
+
+        for (i, time) in result["times"].as_vec().unwrap().iter().enumerate() {
+            let mut record = RunRecord::default();
+            record.insert(WALL_TIME, statistics::yaml_number(time), "s");
+            if let Some(exit_code) = exit_codes.and_then(|codes| codes.get(i)) {
+                // runs that were killed by a signal have no exit code
+                let exit_code = exit_code.as_i64().map(|code| code as i32);
+                let outcome = match exit_code {
+                    Some(0) => RunOutcome::Exited,
+                    _ => RunOutcome::Failed,
+                };
+                record.status = Some(RunStatus {
+                    outcome,
+                    exit_code,
+                    signal: None,
+                    message: None,
+                });
+            }
+            case.runs.push(record);
+        }
+        if let Some(parameters) = result["parameters"].as_hash() {
+            for (param, value) in parameters {
+                let value = match *value {
+                    Yaml::String(ref s) => s.clone(),
+                    ref number => statistics::yaml_number(number).to_string(),
+                };
+                case.parameters.insert(param.as_str().unwrap().to_string(), value);
+            }
+        }
+        results.insert(name.to_string(), case);
+    }
+    results
+}
+
+/// Every sample is a run with the time of one iteration. criterion measures in nanoseconds, the
+/// wall time is in seconds like for every other case.
+fn from_criterion_sample(doc: &Yaml) -> CaseResult {
+    let iters = doc["iters"].as_vec().unwrap();
+    let times = doc["times"].as_vec().unwrap();
+    assert_eq!(iters.len(), times.len(), "criterion sample with more times than iterations!");
+
+    let mut case = CaseResult::default();
+    for (iter, time) in iters.iter().zip(times) {
+        let mut record = RunRecord::default();
+        let per_iteration = statistics::yaml_number(time) / statistics::yaml_number(iter);
+        record.insert(WALL_TIME, per_iteration / 1e9, "s");
+        case.runs.push(record);
+    }
+    case
+}
+
+/// Name of a criterion benchmark, the `full_id` in the `benchmark.json` next to the samples. The
+/// directories below `criterion` are the name if it is missing.
+fn criterion_name(sample_file: &Path) -> String {
+    let baseline_dir = sample_file.parent().unwrap_or_else(|| Path::new(""));
+    let full_id = fs::read_to_string(baseline_dir.join("benchmark.json")).ok()
+        .and_then(|content| json::parse(&content).ok())
+        .and_then(|doc| doc["full_id"].as_str().map(str::to_string));
+    if let Some(full_id) = full_id {
+        return full_id;
+    }
+
+    // the samples are in a directory per baseline, like `new` or `base`
+    let benchmark_dir = baseline_dir.parent().unwrap_or_else(|| Path::new(""));
+    let components: Vec<String> = benchmark_dir.components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let below_criterion = match components.iter().rposition(|c| c == "criterion") {
+        Some(i) => &components[i + 1..],
+        None => &components[components.len().saturating_sub(1)..],
+    };
+    if below_criterion.is_empty() {
+        return sample_file.to_string_lossy().into_owned();
+    }
+    below_criterion.join("/")
+}
+
+/// All benchmarks in a criterion directory like `target/criterion`, with the samples of their
+/// latest run in `new`.
+pub fn from_criterion_directory(directory: &str) -> ResultSet {
+    let mut sample_files = Vec::new();
+    find_samples(Path::new(directory), &mut sample_files);
+    if sample_files.is_empty() {
+        panic!("No criterion benchmarks in {}!", directory);
+    }
+
+    let mut results = ResultSet::new();
+    for file in sample_files {
+        let content = fs::read_to_string(&file).expect("Could not read criterion sample");
+        let doc = match json::parse(&content) {
+            Ok(doc) => doc,
+            Err(e) => panic!("Invalid criterion sample {}: {}", file.display(), e),
+        };
+        results.insert(criterion_name(&file), from_criterion_sample(&doc));
+    }
+    results
+}
+
+fn find_samples(directory: &Path, sample_files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            find_samples(&path, sample_files);
+        } else if path.ends_with("new/sample.json") {
+            sample_files.push(path);
+        }
+    }
+}
+
+
+// --------------------- tests for the imports ---------------------------
+
+#[test]
+fn test_import_hyperfine() {
+    let doc = json::parse(r#"{"results": [
+        {"command": "sleep 0.1", "mean": 0.1, "user": 0.001, "system": 0.001,
+         "times": [0.101, 0.102, 0.103], "exit_codes": [0, 0, 1],
+         "parameters": {"delay": "0.1"}},
+        {"command": "sleep 0.2", "times": [0.2, 0.201]}
+    ]}"#).unwrap();
+
+    let results = from_other_tool("hyperfine.json", &doc).unwrap();
+    let sleep1 = &results["sleep 0.1"];
+
+    assert_eq!(sleep1.samples(WALL_TIME), vec![0.101, 0.102, 0.103]);
+    assert_eq!(sleep1.failures(), 1);
+    assert_eq!(sleep1.runs[2].status.as_ref().unwrap().exit_code, Some(1));
+    assert_eq!(sleep1.parameters["delay"], "0.1");
+    assert_eq!(results["sleep 0.2"].runs.len(), 2);
+    assert_eq!(results["sleep 0.2"].failures(), 0);
+}
+
+#[test]
+fn test_import_criterion_sample() {
+    let doc = json::parse(r#"{"sampling_mode": "Linear", "iters": [10.0, 20.0],
+                             "times": [1000.0, 3000.0]}"#).unwrap();
+
+    let results = from_other_tool("target/criterion/fib/20/new/sample.json", &doc).unwrap();
+
+    assert_eq!(results["fib/20"].samples(WALL_TIME), vec![100e-9, 150e-9]);
+    assert!(from_other_tool("results.json", &json::parse(r#"{"cases": []}"#).unwrap()).is_none());
+}
+
+#[test]
+fn test_criterion_name() {
+    assert_eq!(criterion_name(Path::new("target/criterion/group/bench/new/sample.json")),
+               "group/bench");
+    assert_eq!(criterion_name(Path::new("/tmp/parse/base/sample.json")), "parse");
+}
+
+#[test]
+fn test_import_criterion_directory() {
+    use std::env;
+    use std::process;
+
+    let directory = env::temp_dir().join(format!("macrobm_criterion_{}", process::id()));
+    let new_dir = directory.join("criterion").join("parse").join("new");
+    let base_dir = directory.join("criterion").join("parse").join("base");
+    fs::create_dir_all(&new_dir).unwrap();
+    fs::create_dir_all(&base_dir).unwrap();
+    fs::write(new_dir.join("sample.json"), r#"{"iters": [1.0], "times": [5.0]}"#).unwrap();
+    fs::write(new_dir.join("benchmark.json"), r#"{"full_id": "parse/json"}"#).unwrap();
+    fs::write(base_dir.join("sample.json"), r#"{"iters": [1.0], "times": [9.0]}"#).unwrap();
+
+    let results = from_criterion_directory(directory.to_str().unwrap());
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results["parse/json"].samples(WALL_TIME), vec![5e-9]);
+}
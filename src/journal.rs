@@ -0,0 +1,187 @@
+//! Append every finished run to a journal while a session is running, so an interrupted or
+//! crashed session loses nothing and can be resumed.
+//!
+//! The journal is a stream of Yaml documents. The first one holds the metadata of the session,
+//! every other one a single run. Each document is closed with `...`, a document that was not
+//! written completely is ignored when the journal is read.
+
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+
+use benchmarking::Report;
+use messages;
+use metadata::Metadata;
+use results::{ResultSet, RunRecord, default_unit};
+use statistics;
+
+/// Name of the journal that belongs to a result file.
+pub fn journal_file(result_file: &str) -> String {
+    format!("{}.journal", result_file)
+}
+
+/// Journal of a running session.
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// Start a new journal for a session. An existing journal is never replaced, it holds the
+    /// runs of an interrupted session.
+    pub fn create(file_name: &str, metadata: &Metadata) -> io::Result<Journal> {
+        let file = OpenOptions::new().write(true).create_new(true).open(file_name)?;
+        let mut journal = Journal { file };
+        let mut header = BTreeMap::new();
+        header.insert(Yaml::String("metadata".to_string()), messages::metadata_to_yaml(metadata));
+        journal.write(&Yaml::Hash(header))?;
+        Ok(journal)
+    }
+
+    /// Continue the journal of an earlier session.
+    pub fn append_to(file_name: &str) -> io::Result<Journal> {
+        Ok(Journal { file: OpenOptions::new().append(true).open(file_name)? })
+    }
+
+    /// Record a finished run. Warmup runs are only counted.
+    pub fn append(&mut self, report: &Report) -> io::Result<()> {
+        let mut entry = BTreeMap::new();
+        entry.insert(Yaml::String("case".to_string()), Yaml::String(report.name.clone()));
+        if report.warmup {
+            entry.insert(Yaml::String("warmup".to_string()), Yaml::Boolean(true));
+        } else {
+            entry.insert(Yaml::String("run".to_string()),
+                         messages::run_to_yaml(&RunRecord::from_report(report)));
+        }
+        if let Some(startup) = report.shell_startup {
+            entry.insert(Yaml::String("shell_startup".to_string()),
+                         Yaml::Real(startup.to_string()));
+        }
+        self.write(&Yaml::Hash(entry))
+    }
+
+    /// Write one document at once, so a crash can only cut off the last one.
+    fn write(&mut self, document: &Yaml) -> io::Result<()> {
+        let mut out_str = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut out_str);
+            emitter.dump(document)
+                .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        }
+        out_str.push_str("\n...\n");
+        self.file.write_all(out_str.as_bytes())
+    }
+}
+
+/// Read the runs and the metadata of the session a journal was written by.
+/// Panics if the journal can not be read.
+pub fn read_journal(file_name: &str) -> (ResultSet, Option<Metadata>) {
+    let content = match fs::read_to_string(file_name) {
+        Ok(content) => content,
+        Err(e) => {
+            messages::invalid_filename(file_name);
+            panic!("{:?}", e);
+        }
+    };
+    journal_from_str(&content)
+}
+
+fn journal_from_str(content: &str) -> (ResultSet, Option<Metadata>) {
+    let mut results = ResultSet::new();
+    let mut metadata = None;
+
+    for document in complete_documents(content) {
+        let entry = match YamlLoader::load_from_str(document) {
+            Ok(ref docs) if !docs.is_empty() => docs[0].clone(),
+            _ => continue,
+        };
+        if !entry["metadata"].is_badvalue() {
+            metadata = Some(statistics::metadata_from_yaml(&entry["metadata"]));
+            continue;
+        }
+
+        let name = match entry["case"].as_str() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let case = results.entry(name).or_default();
+        if entry["warmup"].as_bool().unwrap_or(false) {
+            case.warmup += 1;
+            continue;
+        }
+        case.runs.push(statistics::run_from_yaml(&entry["run"],
+                                                 |metric| default_unit(metric).to_string()));
+        if let Some(startup) = entry["shell_startup"].as_f64() {
+            case.shell_startup = Some(startup);
+        }
+    }
+    (results, metadata)
+}
+
+/// Documents of the journal that were closed with `...`.
+fn complete_documents(content: &str) -> Vec<&str> {
+    let mut documents = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        offset += line.len();
+        if line.trim_end() == "..." {
+            documents.push(&content[start..offset]);
+            start = offset;
+        }
+    }
+    documents
+}
+
+
+// --------------------- tests for the journal ---------------------------
+
+#[test]
+fn test_journal_roundtrip() {
+    use benchmarking::{Outcome, RunOrder};
+    use results::{RunOutcome, WALL_TIME};
+    use std::env;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{self, ExitStatus};
+    use std::time::Duration;
+
+    let file = env::temp_dir().join(format!("macrobm_journal_{}", process::id()));
+    let file = file.to_str().unwrap();
+    let metadata = Metadata { hostname: "bench01".to_string(), order: Some(RunOrder::RoundRobin),
+                              ..Metadata::default() };
+
+    let mut journal = Journal::create(file, &metadata).unwrap();
+    let existing = Journal::create(file, &Metadata::default()).err().unwrap();
+    assert_eq!(existing.kind(), io::ErrorKind::AlreadyExists);
+    let mut warmup = Report::new("a".to_string(), Duration::from_millis(100),
+                                 Outcome::Exited(ExitStatus::from_raw(0)));
+    warmup.warmup = true;
+    journal.append(&warmup).unwrap();
+    journal.append(&Report::new("a".to_string(), Duration::from_millis(1500),
+                                Outcome::Exited(ExitStatus::from_raw(0)))).unwrap();
+    drop(journal);
+    let mut journal = Journal::append_to(file).unwrap();
+    journal.append(&Report::new("b".to_string(), Duration::from_secs(1),
+                                Outcome::TimedOut)).unwrap();
+
+    let (results, read_metadata) = read_journal(file);
+    fs::remove_file(file).unwrap();
+
+    assert_eq!(read_metadata, Some(metadata));
+    assert_eq!(results["a"].warmup, 1);
+    assert_eq!(results["a"].samples(WALL_TIME), vec![1.5]);
+    assert_eq!(results["b"].runs[0].status.as_ref().unwrap().outcome, RunOutcome::TimedOut);
+}
+
+#[test]
+fn test_journal_cut_off() {
+    let content = "---\ncase: a\nrun:\n  wall: 1.5\n...\n---\ncase: a\nrun:\n  wall: 2";
+    let (results, metadata) = journal_from_str(content);
+
+    assert_eq!(metadata, None);
+    assert_eq!(results["a"].runs.len(), 1);
+}
@@ -0,0 +1,148 @@
+//! Read and write JSON for other tools. JSON values are held as Yaml, so everything that reads
+//! the Yaml result files reads JSON, too.
+
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Tell if a file holds JSON, from its extension or else from its first character.
+pub fn is_json(file_name: &str, content: &str) -> bool {
+    if file_name.ends_with(".json") {
+        return true;
+    }
+    matches!(content.trim_start().chars().next(), Some('{') | Some('['))
+}
+
+/// Parse a JSON document. JSON is Yaml in flow style, so the Yaml loader reads it. Numbers
+/// without fraction and exponent become integers.
+pub fn parse(content: &str) -> Result<Yaml, String> {
+    let mut docs = YamlLoader::load_from_str(content).map_err(|e| e.to_string())?;
+    if docs.is_empty() {
+        return Err("empty document".to_string());
+    }
+    Ok(docs.swap_remove(0))
+}
+
+/// Write a value as JSON, with one entry per line and two spaces of indentation. Numbers that
+/// JSON can not represent, like NaN, are written as null.
+pub fn to_string(value: &Yaml) -> String {
+    let mut out = String::new();
+    write_value(value, 0, &mut out);
+    out.push('\n');
+    out
+}
+
+fn write_value(value: &Yaml, indent: usize, out: &mut String) {
+    match *value {
+        Yaml::Real(ref real) => write_number(real.parse::<f64>().unwrap_or(f64::NAN), out),
+        Yaml::Integer(i) => out.push_str(&i.to_string()),
+        Yaml::String(ref s) => write_string(s, out),
+        Yaml::Boolean(b) => out.push_str(if b { "true" } else { "false" }),
+        Yaml::Array(ref values) if values.is_empty() => out.push_str("[]"),
+        Yaml::Array(ref values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                out.push_str(if i == 0 { "\n" } else { ",\n" });
+                push_indent(indent + 1, out);
+                write_value(value, indent + 1, out);
+            }
+            out.push('\n');
+            push_indent(indent, out);
+            out.push(']');
+        }
+        Yaml::Hash(ref hash) if hash.is_empty() => out.push_str("{}"),
+        Yaml::Hash(ref hash) => {
+            out.push('{');
+            for (i, (key, value)) in hash.iter().enumerate() {
+                out.push_str(if i == 0 { "\n" } else { ",\n" });
+                push_indent(indent + 1, out);
+                // JSON only knows strings as keys
+                match *key {
+                    Yaml::String(ref key) => write_string(key, out),
+                    Yaml::Integer(key) => write_string(&key.to_string(), out),
+                    Yaml::Real(ref key) => write_string(key, out),
+                    Yaml::Boolean(key) => write_string(&key.to_string(), out),
+                    _ => write_string("null", out),
+                }
+                out.push_str(": ");
+                write_value(value, indent + 1, out);
+            }
+            out.push('\n');
+            push_indent(indent, out);
+            out.push('}');
+        }
+        Yaml::Null | Yaml::Alias(_) | Yaml::BadValue => out.push_str("null"),
+    }
+}
+
+fn write_number(value: f64, out: &mut String) {
+    if value.is_finite() {
+        out.push_str(&value.to_string());
+    } else {
+        out.push_str("null");
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn push_indent(indent: usize, out: &mut String) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+
+// --------------------- tests for reading and writing JSON ---------------------------
+
+#[test]
+fn test_parse_json() {
+    let doc = parse(r#"{"name": "solve", "runs": [{"wall": 1.5}, {"wall": 2}],
+                       "ok": true, "error": null, "text": "a\"bé😀"}"#).unwrap();
+
+    assert_eq!(doc["name"].as_str(), Some("solve"));
+    assert_eq!(doc["runs"][0]["wall"].as_f64(), Some(1.5));
+    assert_eq!(doc["runs"][1]["wall"].as_i64(), Some(2));
+    assert_eq!(doc["ok"].as_bool(), Some(true));
+    assert_eq!(doc["error"], Yaml::Null);
+    assert_eq!(doc["text"].as_str(), Some("a\"b\u{e9}\u{1f600}"));
+    assert_eq!(parse("[1e-3, -4]").unwrap()[0].as_f64(), Some(0.001));
+    // criterion writes its files without any whitespace
+    assert_eq!(parse(r#"{"iters":[10.0,20.0]}"#).unwrap()["iters"][1].as_f64(), Some(20.0));
+}
+
+#[test]
+fn test_parse_invalid_json() {
+    assert!(parse(r#"{"a": 1"#).is_err());
+    assert!(parse("[1, 2] 3").is_err());
+    assert!(parse("").is_err());
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let doc = parse(r#"{"cases": [{"a": {"runs": [{"wall": 0.25, "outcome": "exited"}]}}],
+                       "empty": [], "none": {}}"#).unwrap();
+    let written = to_string(&doc);
+
+    assert_eq!(parse(&written).unwrap(), doc);
+    assert_eq!(to_string(&Yaml::Real("NaN".to_string())), "null\n");
+    assert_eq!(to_string(&Yaml::String("tab\there".to_string())), "\"tab\\there\"\n");
+}
+
+#[test]
+fn test_is_json() {
+    assert!(is_json("results.json", ""));
+    assert!(is_json("results.out", "  {\"format_version\": 2}"));
+    assert!(!is_json("results.yml", "---\nformat_version: 2"));
+}
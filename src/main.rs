@@ -7,7 +7,7 @@
 
 // command line parser
 extern crate clap;
-use clap::{Arg, App, SubCommand};
+use clap::{Arg, App, ArgMatches, SubCommand};
 
 // colored output
 extern crate term_painter;
@@ -21,6 +21,12 @@ extern crate yaml_rust;
 // link with statistics library
 extern crate stat;
 
+// process and signal handling that std does not provide
+extern crate libc;
+
+// check the output of the benchmarked commands
+extern crate regex;
+
 
 // Toplevel operations are wrapped here.
 mod wrappers;
@@ -31,8 +37,28 @@ mod messages;
 mod config;
 // functions to do benchmarking
 mod benchmarking;
+// data model for the measurements of all runs
+mod results;
+// machine and session a result file was produced on
+mod metadata;
+// journal of the finished runs of a session
+mod journal;
 // statistics for the durations
 mod statistics;
+// read and write JSON
+mod json;
+// export runs and statistics for other tools
+mod export;
+// results of other benchmark tools
+mod import;
+// significance tests for the difference of two runs
+mod significance;
+// seeded random numbers for reproducible results
+mod random;
+// pin processes to CPUs
+mod affinity;
+// performance requirements checked against the statistics
+mod requirements;
 
 fn main() {
     // ---------------- Configuration for the command line parser
@@ -40,22 +66,56 @@ fn main() {
         .version("v0.4.5")
         .author("Jonas Toth <development@jonas-toth.eu>")
         .about("Times execution time of commands and produces statistics.")
-        .arg(Arg::with_name("config")
-             .value_name("FILE")
-             .help("Configuration for the macro benchmarks. Default: benchmarks.yml"))
-        .arg(Arg::with_name("jobs")
-             .short("j")
-             .takes_value(true)
-             .help("Control how many thread shall be used to run the benchmarks"))
-        .arg(Arg::with_name("outfile")
-             .short("o")
-             .takes_value(true)
-             .help("Set the filename for the raw data output file. Defaults to results.yml"))
+        .args(&run_args())
+        .subcommand(SubCommand::with_name("run")
+                    .about("Run the benchmarks, the same as without a subcommand")
+                    .args(&run_args()))
         .subcommand(SubCommand::with_name("report")
                     .about("Print statistics of a previously run benchmark")
                     .arg(Arg::with_name("input")
                          .takes_value(true)
-                         .help("Filename of the result file wanted to inspect. Defaults to results.yml")))
+                         .help("Filename of the result file wanted to inspect. Defaults to results.yml"))
+                    .arg(Arg::with_name("metric")
+                         .short("m")
+                         .long("metric")
+                         .takes_value(true)
+                         .help("Metric the statistics are calculated for, e.g. user_time or max_rss. Default: wall"))
+                    .arg(Arg::with_name("group_by")
+                         .short("g")
+                         .long("group-by")
+                         .value_name("PARAMETER")
+                         .takes_value(true)
+                         .help("Report the cases grouped by the value of a matrix parameter"))
+                    .arg(Arg::with_name("columns")
+                         .short("c")
+                         .long("columns")
+                         .takes_value(true)
+                         .use_delimiter(true)
+                         .possible_values(messages::COLUMNS)
+                         .help("Comma separated statistics to show. Default: runs,failed,min,avg,avg_ci,reldev,max,median,median_ci"))
+                    .arg(Arg::with_name("format")
+                         .short("f")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["json", "csv"])
+                         .conflicts_with("group_by")
+                         .help("Print the statistics as JSON or CSV instead of a table, with all columns unless selected"))
+                    .args(&analysis_args()))
+        .subcommand(SubCommand::with_name("export")
+                    .about("Export all runs of a result file as JSON or CSV")
+                    .arg(Arg::with_name("input")
+                         .takes_value(true)
+                         .help("Result file that is exported. Defaults to results.yml"))
+                    .arg(Arg::with_name("format")
+                         .short("f")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["json", "csv"])
+                         .help("Format of the export. Default: csv for an output file ending in .csv, json otherwise"))
+                    .arg(Arg::with_name("outfile")
+                         .short("o")
+                         .takes_value(true)
+                         .help("File the export is written to. Default: stdout")))
         .subcommand(SubCommand::with_name("diff")
                     .about("Compare two different result files with same benchmarks and show differences")
                     .arg(Arg::with_name("ground_truth")
@@ -68,15 +128,89 @@ fn main() {
                     .arg(Arg::with_name("tolerance")
                          .short("t")
                          .takes_value(true)
-                         .help("Modify tolerance in percent, to consider values as equal. Default is 2%")))
+                         .help("Modify tolerance in percent, to consider values as equal. Default is 2%"))
+                    .arg(Arg::with_name("metric")
+                         .short("m")
+                         .long("metric")
+                         .takes_value(true)
+                         .help("Metric that is compared, e.g. user_time or max_rss. Default: wall"))
+                    .arg(Arg::with_name("test")
+                         .short("s")
+                         .long("significance-test")
+                         .takes_value(true)
+                         .possible_values(&["welch", "mann-whitney", "none"])
+                         .help("Test if differences are significant. Default: welch"))
+                    .arg(Arg::with_name("alpha")
+                         .long("alpha")
+                         .takes_value(true)
+                         .help("Significance level for the test. Default: 0.05"))
+                    .args(&analysis_args()))
+        .subcommand(SubCommand::with_name("who-wins")
+                    .about("Compare any number of result files and show which one wins each case")
+                    .arg(Arg::with_name("results")
+                         .required(true)
+                         .multiple(true)
+                         .min_values(2)
+                         .takes_value(true)
+                         .help("Result files that compete against each other"))
+                    .arg(Arg::with_name("tolerance")
+                         .short("t")
+                         .takes_value(true)
+                         .help("Modify tolerance in percent, to consider values as equal. Default is 2%"))
+                    .arg(Arg::with_name("metric")
+                         .short("m")
+                         .long("metric")
+                         .takes_value(true)
+                         .help("Metric that is compared, e.g. user_time or max_rss. Default: wall")))
+        .subcommand(SubCommand::with_name("check")
+                    .about("Check the results against the performance requirements. Exits with 1 if any requirement is not met")
+                    .arg(Arg::with_name("input")
+                         .takes_value(true)
+                         .help("Result file that is checked. Defaults to results.yml"))
+                    .arg(Arg::with_name("requirements")
+                         .short("r")
+                         .long("requirements")
+                         .value_name("FILE")
+                         .takes_value(true)
+                         .help("File with the requirements for the cases. Defaults to benchmarks.yml"))
+                    .arg(Arg::with_name("metric")
+                         .short("m")
+                         .long("metric")
+                         .takes_value(true)
+                         .help("Metric the requirements apply to. Default: wall")))
         .get_matches();
 
     // Handle subcommand for reporting.
     if let Some(sub_report) = matches.subcommand_matches("report") {
         let result_file = sub_report.value_of("input")
             .unwrap_or("results.yml");
+        let metric = sub_report.value_of("metric").unwrap_or(results::WALL_TIME);
+
+        let group_by = sub_report.value_of("group_by");
+        let analysis = analysis_settings(sub_report);
+        let format = sub_report.value_of("format").and_then(export::Format::from_name);
+        let columns: Vec<&str> = match sub_report.values_of("columns") {
+            Some(columns) => columns.collect(),
+            // exports are read by programs, they get everything
+            None if format.is_some() => messages::COLUMNS.to_vec(),
+            None => messages::DEFAULT_COLUMNS.to_vec(),
+        };
 
-        let return_code = wrappers::reporting_process(&result_file);
+        let return_code = wrappers::reporting_process(result_file, metric, group_by,
+                                                      &analysis, &columns, format);
+        std::process::exit(return_code);
+    }
+    // Export the runs for other tools
+    else if let Some(sub_export) = matches.subcommand_matches("export") {
+        let result_file = sub_export.value_of("input").unwrap_or("results.yml");
+        let out_file = sub_export.value_of("outfile");
+        let format = match sub_export.value_of("format") {
+            Some(format) => export::Format::from_name(format).unwrap(),
+            None if out_file.is_some_and(|f| f.ends_with(".csv")) => export::Format::Csv,
+            None => export::Format::Json,
+        };
+
+        let return_code = wrappers::export_process(result_file, format, out_file);
         std::process::exit(return_code);
     }
     // Compare different runs between each other
@@ -86,22 +220,115 @@ fn main() {
             .unwrap_or("results.yml");
         let tolerance = sub_diff.value_of("tolerance").unwrap_or("2.")
             .parse::<f64>().unwrap();
+        let metric = sub_diff.value_of("metric").unwrap_or(results::WALL_TIME);
+        let test = significance::Test::from_name(sub_diff.value_of("test").unwrap_or("welch"));
+        let alpha = sub_diff.value_of("alpha").unwrap_or("0.05")
+            .parse::<f64>().unwrap();
+        let analysis = analysis_settings(sub_diff);
 
         let return_code = wrappers::diff_process(ground_truth, result_file,
-                                                 tolerance);
+                                                 tolerance, metric, test, alpha, &analysis);
+        std::process::exit(return_code);
+    }
+    // Rank many runs against each other
+    else if let Some(sub_wins) = matches.subcommand_matches("who-wins") {
+        let result_files: Vec<&str> = sub_wins.values_of("results").unwrap().collect();
+        let tolerance = sub_wins.value_of("tolerance").unwrap_or("2.")
+            .parse::<f64>().unwrap();
+        let metric = sub_wins.value_of("metric").unwrap_or(results::WALL_TIME);
+
+        let return_code = wrappers::who_wins_process(&result_files, tolerance, metric);
+        std::process::exit(return_code);
+    }
+    // Check the results against requirements, e.g. in CI
+    else if let Some(sub_check) = matches.subcommand_matches("check") {
+        let result_file = sub_check.value_of("input").unwrap_or("results.yml");
+        let requirements_file = sub_check.value_of("requirements")
+            .unwrap_or("benchmarks.yml");
+        let metric = sub_check.value_of("metric").unwrap_or(results::WALL_TIME);
+
+        let return_code = wrappers::check_process(result_file, requirements_file, metric);
         std::process::exit(return_code);
     }
     // Default usage, run benchmarks.
     else {
+        let run = matches.subcommand_matches("run").unwrap_or(&matches);
         // ---------------- Read configuration for the benchmarks
-        let cfg_file = matches.value_of("config")
+        let cfg_file = run.value_of("config")
             .unwrap_or("benchmarks.yml");
-        let n_workers = matches.value_of("jobs").unwrap_or("1")
+        let n_workers = run.value_of("jobs").unwrap_or("1")
             .parse::<usize>().unwrap();
-        let result_file = matches.value_of("outfile").unwrap_or("results.yml");
+        let result_file = run.value_of("outfile").unwrap_or("results.yml");
 
-        let return_code = wrappers::benchmarking_process(&cfg_file, n_workers,
-                                                         result_file);
+        let exclusive_cpus = run.is_present("exclusive_cpus");
+        let resume = run.is_present("resume");
+
+        let return_code = wrappers::benchmarking_process(cfg_file, n_workers, exclusive_cpus,
+                                                         resume, result_file);
         std::process::exit(return_code);
     }
 }
+
+/// Arguments to run the benchmarks, with or without the `run` subcommand.
+fn run_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("config")
+             .value_name("FILE")
+             .help("Configuration for the macro benchmarks. Default: benchmarks.yml"),
+         Arg::with_name("jobs")
+             .short("j")
+             .takes_value(true)
+             .help("Control how many thread shall be used to run the benchmarks"),
+         Arg::with_name("exclusive_cpus")
+             .long("exclusive-cpus")
+             .help("Give every thread its own set of CPUs and pin the runs to it"),
+         Arg::with_name("outfile")
+             .short("o")
+             .takes_value(true)
+             .help("Set the filename for the raw data output file. Defaults to results.yml"),
+         Arg::with_name("resume")
+             .long("resume")
+             .help("Continue an interrupted session from the journal of the output file and only do the missing runs")]
+}
+
+/// Arguments for the statistical analysis, shared by all subcommands that show statistics.
+fn analysis_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("confidence")
+             .long("confidence")
+             .takes_value(true)
+             .help("Confidence level of the intervals in percent. Default: 95"),
+         Arg::with_name("resamples")
+             .long("resamples")
+             .takes_value(true)
+             .help("Number of bootstrap resamples for the intervals. Default: 1000"),
+         Arg::with_name("seed")
+             .long("seed")
+             .takes_value(true)
+             .help("Seed for the bootstrap resampling. Default: 42"),
+         Arg::with_name("outliers")
+             .long("outliers")
+             .takes_value(true)
+             .possible_values(&["tukey", "mad"])
+             .help("Method to detect outliers, Tukey's fences or the median absolute deviation. Default: tukey"),
+         Arg::with_name("exclude_outliers")
+             .long("exclude-outliers")
+             .help("Calculate the statistics without the outliers, the result file is not changed"),
+         Arg::with_name("include_failed")
+             .long("include-failed")
+             .help("Calculate the statistics with the runs that failed or exited with an error")]
+}
+
+fn analysis_settings(matches: &ArgMatches) -> statistics::Analysis {
+    let default = statistics::Analysis::default();
+    statistics::Analysis {
+        confidence: matches.value_of("confidence")
+            .map(|c| c.parse::<f64>().unwrap()).unwrap_or(default.confidence),
+        resamples: matches.value_of("resamples")
+            .map(|r| r.parse::<usize>().unwrap()).unwrap_or(default.resamples),
+        seed: matches.value_of("seed")
+            .map(|s| s.parse::<u64>().unwrap()).unwrap_or(default.seed),
+        outliers: matches.value_of("outliers")
+            .and_then(statistics::OutlierMethod::from_name).unwrap_or(default.outliers),
+        exclude_outliers: matches.is_present("exclude_outliers"),
+        include_failed: matches.is_present("include_failed"),
+    }
+}
@@ -13,9 +13,15 @@ use std::collections::BTreeMap;
 
 use std::time::Duration;
 
-use benchmarking::Report;
+use benchmarking::{Report, Outcome, RunConfig, RunOrder, StopReason};
+use affinity;
+use results::{ResultSet, RunRecord};
+use metadata::{Metadata, FORMAT_VERSION};
+use requirements::CheckResult;
 use statistics;
-use statistics::{BMStatistics, Comparison};
+use wrappers::RunSummary;
+use statistics::{BMStatistics, Comparison, ComparisonResult};
+use significance::Test;
 
 
 /// Banner printed in every programm run.
@@ -25,41 +31,202 @@ pub fn intro(worker: usize) {
              worker);
 }
 
-/// Output run statistics either collected or read in from a result file.
-pub fn report_statistics(stats: &BTreeMap<String, BMStatistics>) -> i32 {
-    println!("{:^6} {:^10} {:^10} {:^7} {:^10} {:<20}",
-             Blue.bold().paint("Runs"),
-             Blue.bold().paint("Min"),
-             Blue.bold().paint("Avg"),
-             Blue.bold().paint("Dev"),
-             Blue.bold().paint("Max"),
-             Blue.bold().paint("Name"));
+/// Tell in which order the runs are started, if it is not the plain one.
+pub fn run_order(order: RunOrder) {
+    match order {
+        RunOrder::Sequential => (),
+        RunOrder::RoundRobin => println!("Runs of all cases are started {}",
+                                         Bold.paint("round-robin")),
+        RunOrder::Shuffled(seed) => println!("Runs of all cases are {} with seed {}",
+                                             Bold.paint("shuffled"), Bold.paint(seed)),
+    }
+}
+
+/// Tell how many CPUs every thread has for itself.
+pub fn exclusive_cpus(per_thread: usize) {
+    println!("Every thread runs on {} CPUs of its own", Bold.paint(per_thread));
+}
+
+/// Columns the statistics table can show.
+pub const COLUMNS: &[&str] = &["runs", "failed", "min", "max", "avg", "avg_ci", "dev", "reldev",
+                               "absdev", "median", "median_ci", "p5", "p25", "p75", "p95", "p99",
+                               "iqr", "outliers"];
+/// Columns shown if none are selected.
+pub const DEFAULT_COLUMNS: &[&str] = &["runs", "failed", "min", "avg", "avg_ci", "reldev", "max",
+                                       "median", "median_ci", "outliers"];
+
+/// Output run statistics either collected or read in from a result file. `failures` are the
+/// runs of every case that did not succeed. `columns` are shown in the given order,
+/// `confidence` is the level of the intervals in percent.
+pub fn report_statistics(stats: &BTreeMap<String, BMStatistics>,
+                         failures: &BTreeMap<String, usize>, confidence: f64,
+                         columns: &[&str]) -> i32 {
+    for column in columns {
+        print!("{} ", Blue.bold().paint(column_header(column, confidence)));
+    }
+    println!("{:<20}", Blue.bold().paint("Name"));
 
     for (bm_name, stat) in stats {
-        let reldev = 100. * stat.dev / stat.avg;
-        println!("{:^6} {:^10.2} {:^10.2} +-{:^4.1}% {:^10.2} {:<20}",
-                 stat.count,
-                 stat.min,
-                 Bold.paint(stat.avg),
-                 reldev,
-                 stat.max,
-                 Bold.paint(bm_name));
+        let failed = failures.get(bm_name).cloned().unwrap_or(0);
+        for column in columns {
+            let cell = column_cell(column, stat, failed);
+            if *column == "avg" {
+                print!("{} ", Bold.paint(cell));
+            } else if *column == "failed" && failed > 0 {
+                print!("{} ", Red.bold().paint(cell));
+            } else {
+                print!("{} ", cell);
+            }
+        }
+        println!("{:<20}", Bold.paint(bm_name));
+    }
+
+    // cases without a single successful run have no statistics
+    for (bm_name, &failed) in failures {
+        if failed > 0 && !stats.contains_key(bm_name) {
+            println!("{} {} has {} failed runs and no statistics",
+                     Red.bold().paint("Failed"),
+                     Bold.paint(bm_name),
+                     Red.paint(failed));
+        }
     }
 
-    return 0;
+    0
 }
 
-/// Print out how two runs differ. With nice coloring where changes are.
+fn column_header(column: &str, confidence: f64) -> String {
+    match column {
+        "runs" => format!("{:^6}", "Runs"),
+        "failed" => format!("{:^6}", "Failed"),
+        "reldev" => format!("{:^7}", "Dev"),
+        "avg_ci" | "median_ci" => format!("{:^16}", format!("{}% CI", confidence)),
+        "dev" => format!("{:^10}", "StdDev"),
+        "absdev" => format!("{:^10}", "AbsDev"),
+        "iqr" => format!("{:^10}", "IQR"),
+        "outliers" => format!("{:^10}", "Outl. m/s"),
+        _ => {
+            let mut name = column.to_string();
+            name[..1].make_ascii_uppercase();
+            format!("{:^10}", name)
+        }
+    }
+}
+
+fn column_cell(column: &str, stat: &BMStatistics, failed: usize) -> String {
+    let value = match column {
+        "runs" => return format!("{:^6}", stat.count),
+        "failed" => return format!("{:^6}", failed),
+        "reldev" => return format!("+-{:^4.1}%", statistics::calc_relative_variance(stat)),
+        "avg_ci" => return format!("{:^16}", interval(stat.avg_ci)),
+        "median_ci" => return format!("{:^16}", interval(stat.median_ci)),
+        "outliers" => {
+            return format!("{:^10}", format!("{} / {}", stat.mild_outliers, stat.severe_outliers))
+        }
+        "min" => stat.min,
+        "max" => stat.max,
+        "avg" => stat.avg,
+        "dev" => stat.dev,
+        "absdev" => stat.absdev,
+        "median" => stat.median,
+        "p5" => stat.p5,
+        "p25" => stat.p25,
+        "p75" => stat.p75,
+        "p95" => stat.p95,
+        "p99" => stat.p99,
+        "iqr" => stat.iqr,
+        _ => panic!("Unknown column {:?}!", column),
+    };
+    format!("{:^10.2}", value)
+}
+
+/// Output if the requirements of every case are met.
+pub fn report_check(checks: &BTreeMap<String, Vec<CheckResult>>) {
+    println!("{:^6} {:<20} {:<40} {:^10} {:^10}",
+             Blue.bold().paint("Result"),
+             Blue.bold().paint("Name"),
+             Blue.bold().paint("Requirement"),
+             Blue.bold().paint("Actual"),
+             Blue.bold().paint("Limit"));
+
+    for (bm_name, case_checks) in checks {
+        for check in case_checks {
+            let result = if check.passed {
+                Green.bold().paint("PASS")
+            } else {
+                Red.bold().paint("FAIL")
+            };
+            let actual = match check.actual {
+                Some(a) => format!("{:.2}", a),
+                None => "no data".to_string(),
+            };
+            println!("{:^6} {:<20} {:<40} {:^10} {:^10.2}",
+                     result,
+                     Bold.paint(bm_name),
+                     check.requirement,
+                     actual,
+                     check.limit);
+        }
+    }
+
+    let failed = checks.values().flat_map(|c| c.iter()).filter(|c| !c.passed).count();
+    if failed == 0 {
+        println!("{}", Green.bold().paint("All requirements are met."));
+    } else {
+        println!("{} requirements are not met!", Red.bold().paint(failed));
+    }
+}
+
+/// Heading for one group of cases that share the value of a matrix parameter.
+pub fn group_heading(parameter: &str, value: &Option<String>) {
+    match *value {
+        Some(ref v) => println!("{} {} {}", Blue.bold().paint(parameter), Blue.paint("="),
+                                Bold.paint(v)),
+        None => println!("{} {}", Blue.bold().paint(parameter), Blue.paint("not set")),
+    }
+}
+
+/// Output the average resource usage of every case.
+pub fn report_usage(stats: &BTreeMap<String, BTreeMap<String, BMStatistics>>) {
+    println!("{:^10} {:^10} {:^12} {:^10} {:^10} {:^10} {:^10} {:<20}",
+             Blue.bold().paint("User"),
+             Blue.bold().paint("Sys"),
+             Blue.bold().paint("MaxRSS(kB)"),
+             Blue.bold().paint("MinFlt"),
+             Blue.bold().paint("MajFlt"),
+             Blue.bold().paint("VolCS"),
+             Blue.bold().paint("InvolCS"),
+             Blue.bold().paint("Name"));
+
+    for (bm_name, metrics) in stats {
+        let avg = |metric: &str| metrics.get(metric).map(|s| s.avg).unwrap_or(0.);
+        println!("{:^10.2} {:^10.2} {:^12.0} {:^10.0} {:^10.0} {:^10.0} {:^10.0} {:<20}",
+                 avg("user_time"),
+                 avg("sys_time"),
+                 // the peak memory is more interesting then the average
+                 metrics.get("max_rss").map(|s| s.max).unwrap_or(0.),
+                 avg("minor_faults"),
+                 avg("major_faults"),
+                 avg("voluntary_switches"),
+                 avg("involuntary_switches"),
+                 Bold.paint(bm_name));
+    }
+}
+
+/// Print out how two runs differ. With nice coloring where changes are. The failures of both
+/// runs are shown next to the number of runs.
 pub fn report_diff(gt_stats: &BTreeMap<String, BMStatistics>,
                    result_stat: &BTreeMap<String, BMStatistics>,
-                   tolerance: f64) -> i32 {
-    let comparison = statistics::compare_runs(gt_stats, result_stat, tolerance);
+                   comparison: &BTreeMap<String, ComparisonResult>,
+                   gt_failures: &BTreeMap<String, usize>,
+                   re_failures: &BTreeMap<String, usize>,
+                   confidence: f64) -> i32 {
+    let ci_label = format!("{}% CI", confidence);
 
     for (bm_name, cmp) in comparison {
         // gt = Ground Truth
         // re = Result
-        let gt = gt_stats.get(&bm_name).unwrap();
-        let re = match result_stat.get(&bm_name) {
+        let gt = gt_stats.get(bm_name).unwrap();
+        let re = match result_stat.get(bm_name) {
             Some(stats) => stats,
             None => continue,
         };
@@ -83,34 +250,117 @@ pub fn report_diff(gt_stats: &BTreeMap<String, BMStatistics>,
             Comparison::Equal => (Bold.paint(gt.avg), Bold.paint(re.avg)),
         };
 
+        let failed = |failures: &BTreeMap<String, usize>| {
+            match failures.get(bm_name).cloned().unwrap_or(0) {
+                0 => Plain.paint(0),
+                n => Red.bold().paint(n),
+            }
+        };
+
         let reldev = statistics::calc_relative_variance(gt);
-        print!("{:^6} {:^10.2} {:^10.2} +-{:^4.1}% {:^10.2} {:^20} ",
+        print!("{:^6} {:^6} {:^10.2} {:^10.2} +-{:^4.1}% {:^10.2} {:^20} ",
                gt.count,
+               failed(gt_failures),
                gt_min,
                gt_max,
                reldev,
                gt_avg,
                Bold.paint(bm_name));
         let reldev = statistics::calc_relative_variance(re);
-        print!("{:^10.2} +-{:^4.1}% {:^10.2} {:^10.2} {:^6}",
+        print!("{:^10.2} +-{:^4.1}% {:^10.2} {:^10.2} {:^6} {:^6}",
                re_avg,
                reldev,
                re_min,
                re_max,
+               failed(re_failures),
                re.count);
-        println!("");
+        // p-value and effect size, d is Cohen's d and r the rank-biserial correlation
+        if let Some(ref sig) = cmp.significance {
+            let effect = match sig.test {
+                Test::Welch => "d",
+                Test::MannWhitney => "r",
+            };
+            print!(" {:^8.4} {}={:<+6.2}", sig.p_value, effect, sig.effect_size);
+        }
+        println!();
+        // confidence intervals of both sides below the case
+        println!("{:>56} {:^20} {:<56}",
+                 format!("avg {} med {:.2} {}", interval(gt.avg_ci), gt.median,
+                         interval(gt.median_ci)),
+                 ci_label,
+                 format!("avg {} med {:.2} {}", interval(re.avg_ci), re.median,
+                         interval(re.median_ci)));
     }
 
-    return 0;
+    0
+}
+
+/// Show which of the compared result files wins every statistic of each case. Files are
+/// referred to by their position on the command line.
+pub fn report_who_wins(files: &[&str], rankings: &BTreeMap<String, statistics::Ranking>) {
+    for (i, file) in files.iter().enumerate() {
+        println!("{} {}", Blue.bold().paint(format!("#{}", i + 1)), file);
+    }
+    println!();
+    println!("{:^20} {:^12} {:^12} {:^12} {:^12}",
+             Blue.bold().paint("Name"),
+             Blue.bold().paint("Avg"),
+             Blue.bold().paint("Min"),
+             Blue.bold().paint("Max"),
+             Blue.bold().paint("Dev"));
+
+    for (bm_name, ranking) in rankings {
+        let winners = |w: &Vec<usize>| {
+            if w.len() == ranking.participants {
+                "tie".to_string()
+            } else {
+                w.iter().map(|i| format!("#{}", i + 1)).collect::<Vec<_>>().join(" ")
+            }
+        };
+        println!("{:^20} {:^12} {:^12} {:^12} {:^12}",
+                 Bold.paint(bm_name),
+                 Green.bold().paint(winners(&ranking.avg)),
+                 Green.paint(winners(&ranking.min)),
+                 Green.paint(winners(&ranking.max)),
+                 Green.paint(winners(&ranking.dev)));
+    }
+}
+
+/// Overall number of won statistics for every file, best file first.
+pub fn report_scoreboard(files: &[&str], wins: &[statistics::Wins]) {
+    println!();
+    println!("{:^6} {:<30} {:^6} {:^6} {:^6} {:^6} {:^6}",
+             Blue.bold().paint("Rank"),
+             Blue.bold().paint("File"),
+             Blue.bold().paint("Avg"),
+             Blue.bold().paint("Min"),
+             Blue.bold().paint("Max"),
+             Blue.bold().paint("Dev"),
+             Blue.bold().paint("Total"));
+
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by(|&a, &b| wins[b].total().cmp(&wins[a].total()).then(wins[b].avg.cmp(&wins[a].avg)));
+
+    for (rank, &i) in order.iter().enumerate() {
+        println!("{:^6} {:<30} {:^6} {:^6} {:^6} {:^6} {:^6}",
+                 rank + 1,
+                 format!("#{} {}", i + 1, files[i]),
+                 wins[i].avg,
+                 wins[i].min,
+                 wins[i].max,
+                 wins[i].dev,
+                 Bold.paint(wins[i].total()));
+    }
 }
 
 pub fn intro_diff(gt_filename: &str, res_filename: &str) {
-    print!("{:^47}", Blue.bold().paint(gt_filename));
+    print!("{:^54}", Blue.bold().paint(gt_filename));
     print!("{:22}", Blue.bold().paint("====================="));
-    println!("{:^47}", Blue.bold().paint(res_filename));
+    println!("{:^54}", Blue.bold().paint(res_filename));
 
-    println!("{:^6} {:^10} {:^10} {:^7} {:^10} {:^20} {:^10} {:^7} {:^10} {:^10} {:^6}",
+    println!("{:^6} {:^6} {:^10} {:^10} {:^7} {:^10} {:^20} {:^10} {:^7} {:^10} {:^10} {:^6} {:^6} {:^8} {:^8}",
              Blue.bold().paint("Runs"),
+             Blue.bold().paint("Failed"),
              Blue.bold().paint("Min"),
              Blue.bold().paint("Max"),
              Blue.bold().paint("Dev"),
@@ -120,7 +370,18 @@ pub fn intro_diff(gt_filename: &str, res_filename: &str) {
              Blue.bold().paint("Dev"),
              Blue.bold().paint("Min"),
              Blue.bold().paint("Max"),
-             Blue.bold().paint("Runs"));
+             Blue.bold().paint("Failed"),
+             Blue.bold().paint("Runs"),
+             Blue.bold().paint("p"),
+             Blue.bold().paint("Effect"));
+}
+
+/// Format a confidence interval for the tables.
+fn interval(ci: Option<(f64, f64)>) -> String {
+    match ci {
+        Some(ci) => format!("[{:.2}, {:.2}]", ci.0, ci.1),
+        None => "-".to_string(),
+    }
 }
 
 /// Error message for an invalid configuration file for benchmarks.
@@ -135,27 +396,62 @@ pub fn invalid_yaml(fname: &str) {
     println!("Error while parsing yml file {}!", Red.paint(fname));
 }
 
+/// Error message when invalid json was in a file.
+pub fn invalid_json(fname: &str) {
+    println!("Error while parsing json file {}!", Red.paint(fname));
+}
+
 /// Gets called when a command gets scheduled count-times. Information for user.
-pub fn scheduled_command(name: &str, count: i64) {
-    println!("{} {} for {} runs",
-             Blue.paint("Scheduling"),
-             Bold.paint(name),
-             Bold.paint(count));
+pub fn scheduled_command(name: &str, config: &RunConfig) {
+    let runs = match (config.target_rel_ci, config.time_budget) {
+        (Some(target), _) => format!("{} to {} runs until the CI is within +-{}%",
+                                     Bold.paint(config.min_runs), Bold.paint(config.max_runs),
+                                     target),
+        (None, Some(budget)) => format!("{} to {} runs within {} seconds",
+                                        Bold.paint(config.min_runs),
+                                        Bold.paint(config.max_runs),
+                                        Bold.paint(budget.as_secs())),
+        (None, None) => format!("{} runs", Bold.paint(config.count)),
+    };
+    if config.warmup > 0 {
+        println!("{} {} for {} after {} warmup runs",
+                 Blue.paint("Scheduling"),
+                 Bold.paint(name),
+                 runs,
+                 Bold.paint(config.warmup));
+    } else {
+        println!("{} {} for {}",
+                 Blue.paint("Scheduling"),
+                 Bold.paint(name),
+                 runs);
+    }
 }
 
 /// Gets called whenever one run of a benchmark is finished. Producess progressbar effect
 pub fn finished_program(report: &Report, counter: i64, maximum: i64) {
-    // err_code.success()
-    let name = if report.ecode.success() {
-        Green.bold().paint(&report.name)
-    } else {
-        Red.bold().paint(&report.name)
+    let name = match report.outcome {
+        Outcome::Exited(_) => Green.bold().paint(&report.name),
+        Outcome::Failed(_) => Red.bold().paint(&report.name),
+        Outcome::TimedOut => Yellow.bold().paint(&report.name),
+        Outcome::SpawnError(_) | Outcome::WaitError(_) | Outcome::HookError(_) |
+        Outcome::Invalid(_, _) => Red.bold().paint(&report.name),
+    };
+    let verb = match report.outcome {
+        _ if report.warmup => "warmed up in",
+        Outcome::TimedOut => "timed out after",
+        Outcome::SpawnError(_) => "could not start after",
+        Outcome::WaitError(_) => "got lost after",
+        Outcome::HookError(_) => "had a failing hook after",
+        Outcome::Invalid(_, _) => "was invalid after",
+        Outcome::Failed(_) => "failed after",
+        Outcome::Exited(_) => "took",
     };
     let exec_time = report.duration;
 
     clean_line();
-    print!("\r{} took {:.2} {} {}{}{}{}{}",
+    print!("\r{} {} {:.2} {} {}{}{}{}{}",
            Bold.paint(name),
+           verb,
            Bold.paint(exec_time),
            Bold.paint("seconds"),
            Blue.paint("["),
@@ -164,13 +460,77 @@ pub fn finished_program(report: &Report, counter: i64, maximum: i64) {
            Blue.paint(maximum),
            Blue.paint("]"));
 
-    io::stdout().flush().ok().expect("Could not flush stdout");
+    io::stdout().flush().expect("Could not flush stdout");
 }
 
-pub fn report_runinformation(time: Duration, success_count: i64, fail_count: i64) {
+pub fn report_runinformation(time: Duration, summary: &RunSummary) {
     println!("All benchmarks took {} seconds", Bold.paint(time.as_secs()));
-    println!("{} commands failed", Red.bold().paint(fail_count));
-    println!("{} commands succeeded", Green.bold().paint(success_count));
+    println!("{} commands failed", Red.bold().paint(summary.fails));
+    println!("{} commands timed out", Yellow.bold().paint(summary.timeouts));
+    println!("{} commands succeeded", Green.bold().paint(summary.successes));
+
+    for (name, stop) in &summary.stops {
+        let reason = match stop.reason {
+            StopReason::TargetReached => Green.paint("the target was reached"),
+            StopReason::MaxRuns => Yellow.paint("max_runs were done"),
+            StopReason::TimeBudget => Yellow.paint("the time budget was used up"),
+        };
+        println!("{} stopped after {} runs with a CI of +-{:.2}% because {}",
+                 Bold.paint(name),
+                 stop.runs,
+                 stop.rel_ci,
+                 reason);
+    }
+
+    let invalid: i64 = summary.invalid.values().flat_map(|e| e.values()).sum();
+    if invalid > 0 {
+        println!("{} runs were invalid and are not part of the statistics",
+                 Red.bold().paint(invalid));
+    }
+    for (name, reasons) in &summary.invalid {
+        for (msg, count) in reasons {
+            println!("{} {} {} runs: {}",
+                     Red.bold().paint("Invalid"),
+                     Bold.paint(name),
+                     Red.paint(count),
+                     msg);
+        }
+    }
+
+    let not_run: i64 = summary.errors.values().flat_map(|e| e.values()).sum();
+    if not_run > 0 {
+        println!("{} commands could not be run", Red.bold().paint(not_run));
+    }
+    for (name, errors) in &summary.errors {
+        for (msg, count) in errors {
+            println!("{} {} {} runs: {}",
+                     Red.bold().paint("Could not run"),
+                     Bold.paint(name),
+                     Red.paint(count),
+                     msg);
+        }
+    }
+}
+
+/// Tell that the runs of an earlier session are kept.
+pub fn resume(journal_file: &str, runs: usize) {
+    println!("Resuming the session of {} with {} runs already done",
+             Bold.paint(journal_file),
+             Bold.paint(runs));
+}
+
+/// Gets called when the session was stopped with Ctrl-C before all benchmarks were run.
+pub fn stopped() {
+    clean_line();
+    println!("\r{}", Red.bold().paint("Interrupted, stopped running benchmarks!"));
+}
+
+/// Tell where the runs of an interrupted session are.
+pub fn interrupted(res_file: &str, journal_file: &str) {
+    println!("The runs so far are written to {}, {} keeps them to continue with {}",
+             Bold.paint(res_file),
+             Bold.paint(journal_file),
+             Bold.paint("--resume"));
 }
 
 /// Gets called when all benchmarks were run.
@@ -179,25 +539,9 @@ pub fn finished() {
     println!("\r{}", Blue.bold().paint("Finished running benchmarks.!"));
 }
 
-/// Write the measured times as Yaml to the specified file. Casename is the key, value is a vector
-/// of floats.
-pub fn write_result_file(filename: &str, results: &BTreeMap<String, Vec<f32>>) {
-    let mut case_vec = Vec::new();
-
-    for case in results.keys() {
-        // convert f32 times into yaml real values (strings)
-        let mut yaml_times = Vec::new();
-        for time in results.get(case).unwrap() {
-            yaml_times.push(Yaml::Real(time.to_string()));
-        }
-
-        let mut hash_table = BTreeMap::new();
-        hash_table.insert(Yaml::String(case.clone()), Yaml::Array(yaml_times));
-        // push back the values to the case name
-        case_vec.push(Yaml::Hash(hash_table));
-    }
-
-    let yaml_data = Yaml::Array(case_vec);
+/// Write the measured runs as Yaml to the specified file, see `results_to_yaml` for the layout.
+pub fn write_result_file(filename: &str, results: &ResultSet, metadata: &Metadata) {
+    let yaml_data = results_to_yaml(results, Some(metadata));
     let mut out_str = String::new();
     {
         let mut emitter = YamlEmitter::new(&mut out_str);
@@ -214,8 +558,145 @@ pub fn write_result_file(filename: &str, results: &BTreeMap<String, Vec<f32>>) {
     file.write_all(out_str.as_bytes()).expect("Could not write results");
 }
 
+/// The measured runs as they are stored in the result file. The file is a hash with the
+/// `format_version`, the `metadata` of the session and the list of `cases`. Casename is the key
+/// of every case, value is a hash with the number of `warmup` runs, the matrix `parameters`, the
+/// resolved `config`, the `units` of all metrics and the list of `runs`. Every run maps the
+/// metric names to the measured value, next to how the run ended.
+pub fn results_to_yaml(results: &ResultSet, metadata: Option<&Metadata>) -> Yaml {
+    let mut case_vec = Vec::new();
+
+    for (case, case_result) in results {
+        let mut yaml_units = BTreeMap::new();
+        for (metric, unit) in case_result.units() {
+            yaml_units.insert(Yaml::String(metric), Yaml::String(unit));
+        }
+
+        let yaml_runs = case_result.runs.iter().map(run_to_yaml).collect();
+
+        let mut yaml_parameters = BTreeMap::new();
+        for (param, value) in &case_result.parameters {
+            yaml_parameters.insert(Yaml::String(param.clone()), Yaml::String(value.clone()));
+        }
+
+        let mut case_hash = BTreeMap::new();
+        case_hash.insert(Yaml::String("warmup".to_string()), Yaml::Integer(case_result.warmup));
+        if !yaml_parameters.is_empty() {
+            case_hash.insert(Yaml::String("parameters".to_string()), Yaml::Hash(yaml_parameters));
+        }
+        if let Some(startup) = case_result.shell_startup {
+            case_hash.insert(Yaml::String("shell_startup".to_string()),
+                             Yaml::Real(startup.to_string()));
+        }
+        if let Some(ref config) = case_result.config {
+            case_hash.insert(Yaml::String("config".to_string()), config.clone());
+        }
+        case_hash.insert(Yaml::String("units".to_string()), Yaml::Hash(yaml_units));
+        case_hash.insert(Yaml::String("runs".to_string()), Yaml::Array(yaml_runs));
+
+        let mut hash_table = BTreeMap::new();
+        hash_table.insert(Yaml::String(case.clone()), Yaml::Hash(case_hash));
+        // push back the values to the case name
+        case_vec.push(Yaml::Hash(hash_table));
+    }
+
+    let mut document = BTreeMap::new();
+    document.insert(Yaml::String("format_version".to_string()), Yaml::Integer(FORMAT_VERSION));
+    if let Some(metadata) = metadata {
+        document.insert(Yaml::String("metadata".to_string()), metadata_to_yaml(metadata));
+    }
+    document.insert(Yaml::String("cases".to_string()), Yaml::Array(case_vec));
+    Yaml::Hash(document)
+}
+
+/// Write exported results to the specified file, or to stdout without one.
+pub fn write_export(filename: Option<&str>, content: &str) {
+    match filename {
+        Some(filename) => {
+            let mut file = File::create(filename).expect("Could not open export file");
+            file.write_all(content.as_bytes()).expect("Could not write the export");
+        }
+        None => print!("{}", content),
+    }
+}
+
+/// A run as it is stored in the result file, the metrics with how the run ended.
+pub fn run_to_yaml(run: &RunRecord) -> Yaml {
+    let mut yaml_run = BTreeMap::new();
+    // convert the values into yaml real values (strings)
+    for (metric, value) in &run.metrics {
+        yaml_run.insert(Yaml::String(metric.clone()), Yaml::Real(value.value.to_string()));
+    }
+    if !run.cpus.is_empty() {
+        yaml_run.insert(Yaml::String("cpus".to_string()),
+                        Yaml::String(affinity::format_cpu_list(&run.cpus)));
+    }
+    if let Some(ref status) = run.status {
+        yaml_run.insert(Yaml::String("outcome".to_string()),
+                        Yaml::String(status.outcome.name().to_string()));
+        if let Some(code) = status.exit_code {
+            yaml_run.insert(Yaml::String("exit_code".to_string()), Yaml::Integer(code as i64));
+        }
+        if let Some(signal) = status.signal {
+            yaml_run.insert(Yaml::String("signal".to_string()), Yaml::Integer(signal as i64));
+        }
+        if let Some(ref msg) = status.message {
+            yaml_run.insert(Yaml::String("error".to_string()), Yaml::String(msg.clone()));
+        }
+    }
+    Yaml::Hash(yaml_run)
+}
+
+/// Metadata of a session as it is stored in the result file.
+pub fn metadata_to_yaml(metadata: &Metadata) -> Yaml {
+    let mut hash = BTreeMap::new();
+    let strings = [("macrobm_version", &metadata.macrobm_version),
+                   ("timestamp", &metadata.timestamp),
+                   ("hostname", &metadata.hostname),
+                   ("kernel", &metadata.kernel),
+                   ("cpu_model", &metadata.cpu_model),
+                   ("config_file", &metadata.config_file)];
+    for &(key, value) in &strings {
+        hash.insert(Yaml::String(key.to_string()), Yaml::String(value.clone()));
+    }
+    hash.insert(Yaml::String("cores".to_string()), Yaml::Integer(metadata.cores));
+    hash.insert(Yaml::String("jobs".to_string()), Yaml::Integer(metadata.jobs));
+    if let Some(ref commit) = metadata.git_commit {
+        hash.insert(Yaml::String("git_commit".to_string()), Yaml::String(commit.clone()));
+    }
+    if let Some(order) = metadata.order {
+        hash.insert(Yaml::String("order".to_string()), Yaml::String(order.name().to_string()));
+        // the seed of a shuffled order is needed to repeat the session exactly
+        if let RunOrder::Shuffled(seed) = order {
+            hash.insert(Yaml::String("seed".to_string()), Yaml::Integer(seed as i64));
+        }
+    }
+    Yaml::Hash(hash)
+}
+
+/// Show where and how the results of a file were measured.
+pub fn report_metadata(file: &str, metadata: &Metadata) {
+    println!("{} was measured {} on {} ({}, {} cores of {}) with macrobm {}",
+             Bold.paint(file),
+             metadata.timestamp,
+             Bold.paint(&metadata.hostname),
+             metadata.kernel,
+             metadata.cores,
+             metadata.cpu_model,
+             metadata.macrobm_version);
+    let order = metadata.order.map(|order| order.name()).unwrap_or("sequential");
+    print!("Config {} ran with {} threads in {} order",
+           Bold.paint(&metadata.config_file),
+           metadata.jobs,
+           order);
+    match metadata.git_commit {
+        Some(ref commit) => println!(" at commit {}", commit),
+        None => println!(),
+    }
+}
+
 /// Clean the current line. Used for the progressbar effect.
 fn clean_line() {
     print!("\r                                                                ");
-    io::stdout().flush().ok().expect("Could not flush stdout");
+    io::stdout().flush().expect("Could not flush stdout");
 }
@@ -0,0 +1,132 @@
+//! Describe the machine and the session a result file was produced by, so results can still be
+//! interpreted months later.
+
+use libc;
+use std::ffi::CStr;
+use std::fs;
+use std::mem;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use benchmarking::RunOrder;
+
+/// Version of the result file layout. Files without a version are plain lists of cases.
+pub const FORMAT_VERSION: i64 = 2;
+
+/// Information about a benchmark session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub macrobm_version: String,
+    pub timestamp: String, // start of the session in UTC, like 2024-01-31T12:00:00Z
+    pub hostname: String,
+    pub kernel: String,
+    pub cpu_model: String,
+    pub cores: i64, // online CPUs of the machine
+    pub jobs: i64, // threads the runs were distributed over
+    pub config_file: String,
+    pub git_commit: Option<String>, // commit of the working directory, if it is a repository
+    pub order: Option<RunOrder>,
+}
+
+impl Metadata {
+    /// Describe the current machine for a session that runs `config_file` with `jobs` threads.
+    pub fn collect(config_file: &str, jobs: usize, order: RunOrder) -> Metadata {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Metadata {
+            macrobm_version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: format_timestamp(now.as_secs()),
+            hostname: hostname(),
+            kernel: kernel(),
+            cpu_model: cpu_model(),
+            cores: unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) } as i64,
+            jobs: jobs as i64,
+            config_file: config_file.to_string(),
+            git_commit: git_commit(),
+            order: Some(order),
+        }
+    }
+}
+
+fn hostname() -> String {
+    let mut buffer = [0 as libc::c_char; 256];
+    let ret = unsafe { libc::gethostname(buffer.as_mut_ptr(), buffer.len()) };
+    if ret != 0 {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+/// Name and release of the kernel, e.g. `Linux 6.1.0`.
+fn kernel() -> String {
+    let mut name: libc::utsname = unsafe { mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return String::new();
+    }
+    let field = |value: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(value.as_ptr()) }.to_string_lossy().into_owned()
+    };
+    format!("{} {}", field(&name.sysname), field(&name.release))
+}
+
+fn cpu_model() -> String {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    cpuinfo.lines()
+        .find(|line| line.starts_with("model name"))
+        .and_then(|line| line.split(':').nth(1))
+        .map(|model| model.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Format seconds since the epoch as UTC date and time.
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // civil date from days since 1970-01-01, after Howard Hinnant
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+                       - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day,
+            time / 3600, time % 3600 / 60, time % 60)
+}
+
+
+// --------------------- tests for the metadata ---------------------------
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+    assert_eq!(format_timestamp(951782400), "2000-02-29T00:00:00Z");
+    assert_eq!(format_timestamp(1706702400 + 3723), "2024-01-31T13:02:03Z");
+}
+
+#[test]
+fn test_collect_metadata() {
+    let metadata = Metadata::collect("benchmarks.yml", 4, RunOrder::RoundRobin);
+
+    assert_eq!(metadata.jobs, 4);
+    assert_eq!(metadata.order, Some(RunOrder::RoundRobin));
+    assert!(metadata.cores > 0);
+    assert!(metadata.kernel.starts_with("Linux"));
+    assert!(!metadata.hostname.is_empty());
+}
@@ -0,0 +1,62 @@
+//! Small seeded random number generator. Results that depend on randomness must be reproducible,
+//! so the same seed always gives the same numbers on every platform.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SplitMix64 generator, fast and good enough for resampling and shuffling.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed index in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0);
+        // the modulo bias is negligible for the small ranges used here
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Seed for results that only need to be reproducible once it is recorded. It stays below 2^63,
+/// so it fits into a yaml integer.
+pub fn seed_from_clock() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Rng::new(now.as_secs() ^ u64::from(now.subsec_nanos())).next_u64() >> 1
+}
+
+
+// --------------------- tests for the random number generator ---------------------------
+
+#[test]
+fn test_rng_reproducible() {
+    let mut rng1 = Rng::new(42);
+    let mut rng2 = Rng::new(42);
+    let numbers: Vec<u64> = (0..5).map(|_| rng1.next_u64()).collect();
+    assert_eq!(numbers, (0..5).map(|_| rng2.next_u64()).collect::<Vec<_>>());
+
+    // reference value of SplitMix64 for seed 0
+    assert_eq!(Rng::new(0).next_u64(), 0xe220a8397b1dcdaf);
+}
+
+#[test]
+fn test_rng_below() {
+    let mut rng = Rng::new(7);
+    let mut seen = [false; 5];
+    for _ in 0..100 {
+        seen[rng.below(5)] = true;
+    }
+    assert!(seen.iter().all(|&s| s));
+}
@@ -0,0 +1,132 @@
+//! Performance requirements a benchmark has to meet, e.g. to gate a CI pipeline.
+
+use statistics::{BMStatistics, calc_relative_variance};
+
+/// A single requirement for the statistics of one case.
+// the variants are named like the keys in the configuration
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Requirement {
+    /// The average must not be above this value.
+    MaxAvg(f64),
+    /// The fastest run must not be above this value.
+    MaxMin(f64),
+    /// The slowest run must not be above this value.
+    MaxMax(f64),
+    /// The relative deviation must not be above this many percent.
+    MaxRelDev(f64),
+    /// The average must not be more than this many percent above the average of the same case
+    /// in the baseline result file.
+    MaxRegressionVs(String, f64),
+}
+
+impl Requirement {
+    /// Key of the requirement in the configuration, requirements with the same key replace each
+    /// other.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Requirement::MaxAvg(_) => "max_avg",
+            Requirement::MaxMin(_) => "max_min",
+            Requirement::MaxMax(_) => "max_max",
+            Requirement::MaxRelDev(_) => "max_rel_dev",
+            Requirement::MaxRegressionVs(_, _) => "max_regression_vs",
+        }
+    }
+
+    /// Human readable form for the check report.
+    pub fn describe(&self) -> String {
+        match *self {
+            Requirement::MaxRegressionVs(ref baseline, _) => {
+                format!("max_regression_vs {}", baseline)
+            }
+            _ => self.key().to_string(),
+        }
+    }
+}
+
+/// Outcome of checking one requirement.
+#[derive(Debug)]
+pub struct CheckResult {
+    pub requirement: String,
+    /// Measured value, None if there was nothing to measure against.
+    pub actual: Option<f64>,
+    pub limit: f64,
+    pub passed: bool,
+}
+
+/// Check one requirement against the statistics of a case. `baseline` are the statistics of the
+/// same case in the baseline file of a `MaxRegressionVs` requirement.
+pub fn check(requirement: &Requirement, stats: Option<&BMStatistics>,
+             baseline: Option<&BMStatistics>) -> CheckResult {
+    let (actual, limit) = match *requirement {
+        Requirement::MaxAvg(limit) => (stats.map(|s| s.avg), limit),
+        Requirement::MaxMin(limit) => (stats.map(|s| s.min), limit),
+        Requirement::MaxMax(limit) => (stats.map(|s| s.max), limit),
+        Requirement::MaxRelDev(limit) => (stats.map(calc_relative_variance), limit),
+        Requirement::MaxRegressionVs(_, limit) => {
+            let regression = match (stats, baseline) {
+                (Some(s), Some(b)) => Some(100. * (s.avg - b.avg) / b.avg),
+                _ => None,
+            };
+            (regression, limit)
+        }
+    };
+
+    CheckResult {
+        requirement: requirement.describe(),
+        actual,
+        limit,
+        passed: actual.map(|a| a <= limit).unwrap_or(false),
+    }
+}
+
+
+// --------------------- tests for checking requirements ---------------------------
+
+#[allow(dead_code)]
+fn test_stats(avg: f64, dev: f64) -> BMStatistics {
+    BMStatistics {
+        avg,
+        min: avg - 0.1,
+        max: avg + 0.1,
+        dev,
+        count: 10,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_check_limits() {
+    let stats = test_stats(1.0, 0.02);
+
+    assert!(check(&Requirement::MaxAvg(1.2), Some(&stats), None).passed);
+    assert!(!check(&Requirement::MaxAvg(0.8), Some(&stats), None).passed);
+    assert!(check(&Requirement::MaxMin(0.9), Some(&stats), None).passed);
+    assert!(!check(&Requirement::MaxMax(1.0), Some(&stats), None).passed);
+    assert!(check(&Requirement::MaxRelDev(3.), Some(&stats), None).passed);
+    assert!(!check(&Requirement::MaxRelDev(1.), Some(&stats), None).passed);
+}
+
+#[test]
+fn test_check_regression() {
+    let requirement = Requirement::MaxRegressionVs("baseline.yml".to_string(), 5.);
+    let baseline = test_stats(1.0, 0.);
+
+    let result = check(&requirement, Some(&test_stats(1.04, 0.)), Some(&baseline));
+    assert!(result.passed);
+    assert_eq!(result.requirement, "max_regression_vs baseline.yml");
+
+    let result = check(&requirement, Some(&test_stats(1.1, 0.)), Some(&baseline));
+    assert!(!result.passed);
+    assert!((result.actual.unwrap() - 10.).abs() < 1e-9);
+}
+
+#[test]
+fn test_check_without_data_fails() {
+    let requirement = Requirement::MaxRegressionVs("baseline.yml".to_string(), 5.);
+    let result = check(&requirement, Some(&test_stats(1.0, 0.)), None);
+    assert!(!result.passed);
+    assert!(result.actual.is_none());
+
+    assert!(!check(&Requirement::MaxAvg(1.), None, None).passed);
+}
@@ -0,0 +1,395 @@
+//! Data model for everything that is measured during a benchmark session. Every run stores an
+//! open set of named metrics, statistics and comparisons pick the metric they work on.
+
+use std::collections::BTreeMap;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use yaml_rust::Yaml;
+
+use benchmarking::{Outcome, Report, ResourceUsage, RunOrder, USAGE_METRICS};
+
+/// Name of the metric the wall clock time of a run is stored as.
+pub const WALL_TIME: &str = "wall";
+
+/// Unit of the metrics macrobm measures itself, custom metrics have no default unit.
+pub fn default_unit(metric: &str) -> &'static str {
+    if metric == WALL_TIME {
+        return "s";
+    }
+    ResourceUsage::default().metrics().iter()
+        .find(|&&(name, _, _)| name == metric)
+        .map(|&(_, _, unit)| unit)
+        .unwrap_or("")
+}
+
+/// A single measured value together with its unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub value: f64,
+    pub unit: String,
+}
+
+/// How a run ended, the kind of `benchmarking::Outcome` without its details.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunOutcome {
+    Exited,
+    Failed,
+    TimedOut,
+    Invalid,
+    SpawnError,
+    WaitError,
+    HookError,
+}
+
+impl RunOutcome {
+    pub fn from_name(name: &str) -> Option<RunOutcome> {
+        match name {
+            "exited" => Some(RunOutcome::Exited),
+            "failed" => Some(RunOutcome::Failed),
+            "timed_out" => Some(RunOutcome::TimedOut),
+            "invalid" => Some(RunOutcome::Invalid),
+            "spawn_error" => Some(RunOutcome::SpawnError),
+            "wait_error" => Some(RunOutcome::WaitError),
+            "hook_error" => Some(RunOutcome::HookError),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RunOutcome::Exited => "exited",
+            RunOutcome::Failed => "failed",
+            RunOutcome::TimedOut => "timed_out",
+            RunOutcome::Invalid => "invalid",
+            RunOutcome::SpawnError => "spawn_error",
+            RunOutcome::WaitError => "wait_error",
+            RunOutcome::HookError => "hook_error",
+        }
+    }
+}
+
+/// How a run ended, with the exit code or the signal that stopped the process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunStatus {
+    pub outcome: RunOutcome,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub message: Option<String>, // why the run failed or was invalid
+}
+
+impl RunStatus {
+    pub fn from_outcome(outcome: &Outcome) -> RunStatus {
+        let status = |outcome, message: Option<&String>| RunStatus {
+            outcome,
+            exit_code: None,
+            signal: None,
+            message: message.cloned(),
+        };
+        let exited = |outcome, ecode: &ExitStatus, message| RunStatus {
+            exit_code: ecode.code(),
+            signal: ecode.signal(),
+            ..status(outcome, message)
+        };
+        match *outcome {
+            Outcome::Exited(ref ecode) => exited(RunOutcome::Exited, ecode, None),
+            Outcome::Failed(ref ecode) => exited(RunOutcome::Failed, ecode, None),
+            Outcome::Invalid(ref ecode, ref msg) => exited(RunOutcome::Invalid, ecode, Some(msg)),
+            Outcome::TimedOut => status(RunOutcome::TimedOut, None),
+            Outcome::SpawnError(ref msg) => status(RunOutcome::SpawnError, Some(msg)),
+            Outcome::WaitError(ref msg) => status(RunOutcome::WaitError, Some(msg)),
+            Outcome::HookError(ref msg) => status(RunOutcome::HookError, Some(msg)),
+        }
+    }
+}
+
+/// Everything that was measured during one run of a benchmark, mapped by metric name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunRecord {
+    pub metrics: BTreeMap<String, Metric>,
+    pub cpus: Vec<usize>, // CPUs the run was pinned to, empty if it was not
+    pub status: Option<RunStatus>, // unknown in older files
+}
+
+impl RunRecord {
+    /// Create the record for a run. Runs that exited, successfully or not, contain the wall
+    /// clock time and the resource usage if it is known, the others only how they ended.
+    pub fn from_report(report: &Report) -> RunRecord {
+        let mut record = RunRecord {
+            cpus: report.cpus.clone().unwrap_or_default(),
+            status: Some(RunStatus::from_outcome(&report.outcome)),
+            ..RunRecord::default()
+        };
+        // runs that were killed or did not meet the expectations have no meaningful duration
+        match report.outcome {
+            Outcome::Exited(_) | Outcome::Failed(_) => (),
+            _ => return record,
+        }
+        record.insert(WALL_TIME, report.duration, "s");
+
+        if let Some(ref usage) = report.usage {
+            for (name, value, unit) in usage.metrics() {
+                record.insert(name, value, unit);
+            }
+        }
+        record
+    }
+
+    pub fn insert(&mut self, name: &str, value: f64, unit: &str) {
+        self.metrics.insert(name.to_string(), Metric { value, unit: unit.to_string() });
+    }
+
+    pub fn value(&self, name: &str) -> Option<f64> {
+        self.metrics.get(name).map(|m| m.value)
+    }
+
+    /// Runs of older files do not know how they ended and count as successful.
+    pub fn succeeded(&self) -> bool {
+        self.status.as_ref().map(|s| s.outcome == RunOutcome::Exited).unwrap_or(true)
+    }
+}
+
+/// All measured runs of one benchmark case.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaseResult {
+    pub runs: Vec<RunRecord>,
+    pub warmup: i64, // number of warmup runs, they are not part of `runs`
+    pub parameters: BTreeMap<String, String>, // matrix parameters the case was run with
+    pub order: Option<RunOrder>, // order of the runs of the session, unknown in older files
+    pub shell_startup: Option<f64>, // seconds subtracted from every wall time for the shell
+    pub config: Option<Yaml>, // resolved configuration of the case, unknown in older files
+}
+
+impl CaseResult {
+    /// Values of `metric` for every run that measured it.
+    pub fn samples(&self, metric: &str) -> Vec<f64> {
+        self.runs.iter().filter_map(|r| r.value(metric)).collect()
+    }
+
+    /// Number of runs that did not succeed.
+    pub fn failures(&self) -> usize {
+        self.runs.iter().filter(|r| !r.succeeded()).count()
+    }
+
+    /// Names of all measured metrics with their unit. Known metrics come first, in the order
+    /// they are usually reported, custom ones follow sorted by name.
+    pub fn units(&self) -> Vec<(String, String)> {
+        let mut units = BTreeMap::new();
+        for run in &self.runs {
+            for (name, metric) in &run.metrics {
+                units.entry(name.clone()).or_insert_with(|| metric.unit.clone());
+            }
+        }
+
+        let mut result = Vec::new();
+        for known in Some(WALL_TIME).iter().chain(USAGE_METRICS.iter()) {
+            if let Some(unit) = units.remove(*known) {
+                result.push((known.to_string(), unit));
+            }
+        }
+        result.extend(units);
+        result
+    }
+}
+
+/// Results of a whole benchmark session, mapped by case name.
+pub type ResultSet = BTreeMap<String, CaseResult>;
+
+/// Split the results by the value of a matrix parameter. Cases without that parameter are
+/// grouped under `None`.
+pub fn group_by(results: &ResultSet, parameter: &str) -> BTreeMap<Option<String>, ResultSet> {
+    let mut groups: BTreeMap<Option<String>, ResultSet> = BTreeMap::new();
+
+    for (name, case) in results {
+        groups.entry(case.parameters.get(parameter).cloned()).or_default()
+            .insert(name.clone(), case.clone());
+    }
+    groups
+}
+
+/// The results with only the successful runs of every case.
+pub fn without_failures(results: &ResultSet) -> ResultSet {
+    results.iter()
+        .map(|(name, case)| {
+            let runs = case.runs.iter().filter(|r| r.succeeded()).cloned().collect();
+            (name.clone(), CaseResult { runs, ..case.clone() })
+        })
+        .collect()
+}
+
+/// Number of runs that did not succeed for every case.
+pub fn failures(results: &ResultSet) -> BTreeMap<String, usize> {
+    results.iter().map(|(name, case)| (name.clone(), case.failures())).collect()
+}
+
+/// Names of the metrics any case measured, in the order of `CaseResult::units`.
+pub fn metrics(results: &ResultSet) -> Vec<String> {
+    let mut metrics = Vec::new();
+    for case in results.values() {
+        for (metric, _) in case.units() {
+            if !metrics.contains(&metric) {
+                metrics.push(metric);
+            }
+        }
+    }
+    metrics
+}
+
+/// Extract the values of `metric` for every case that measured it at least once.
+pub fn samples(results: &ResultSet, metric: &str) -> BTreeMap<String, Vec<f64>> {
+    let mut samples = BTreeMap::new();
+
+    for (name, case) in results {
+        let values = case.samples(metric);
+        if !values.is_empty() {
+            samples.insert(name.clone(), values);
+        }
+    }
+    samples
+}
+
+
+// --------------------- tests for the result data model ---------------------------
+
+#[allow(dead_code)]
+fn test_record(wall: f64, rss: f64) -> RunRecord {
+    let mut record = RunRecord::default();
+    record.insert(WALL_TIME, wall, "s");
+    record.insert("max_rss", rss, "kB");
+    record
+}
+
+#[test]
+fn test_record_from_report() {
+    use std::time::Duration;
+
+    let mut report = Report::new("case".to_string(), Duration::from_millis(1500),
+                                 Outcome::Exited(ExitStatus::from_raw(3 << 8)));
+    report.cpus = Some(vec![2, 3]);
+    let record = RunRecord::from_report(&report);
+    let status = record.status.clone().unwrap();
+    assert_eq!(record.value(WALL_TIME), Some(1.5));
+    assert_eq!(record.cpus, vec![2, 3]);
+    assert_eq!((status.outcome, status.exit_code, status.signal),
+               (RunOutcome::Exited, Some(3), None));
+
+    let report = Report::new("case".to_string(), Duration::from_secs(1),
+                             Outcome::Invalid(ExitStatus::from_raw(9),
+                                              "stdout does not match `x`".to_string()));
+    let record = RunRecord::from_report(&report);
+    let status = record.status.clone().unwrap();
+    assert!(record.metrics.is_empty());
+    assert_eq!((status.outcome, status.exit_code, status.signal),
+               (RunOutcome::Invalid, None, Some(9)));
+    assert_eq!(status.message.unwrap(), "stdout does not match `x`");
+
+    for outcome in &[RunOutcome::Exited, RunOutcome::TimedOut, RunOutcome::HookError] {
+        assert_eq!(RunOutcome::from_name(outcome.name()), Some(*outcome));
+    }
+}
+
+#[test]
+fn test_default_unit() {
+    assert_eq!(default_unit(WALL_TIME), "s");
+    assert_eq!(default_unit("sys_time"), "s");
+    assert_eq!(default_unit("max_rss"), "kB");
+    assert_eq!(default_unit("custom"), "");
+}
+
+#[test]
+fn test_case_samples() {
+    let mut case = CaseResult::default();
+    case.runs.push(test_record(1.5, 1024.));
+    case.runs.push(test_record(2.5, 2048.));
+    case.runs.push(RunRecord::default());
+
+    assert_eq!(case.samples(WALL_TIME), vec![1.5, 2.5]);
+    assert_eq!(case.samples("max_rss"), vec![1024., 2048.]);
+    assert!(case.samples("user_time").is_empty());
+}
+
+#[test]
+fn test_case_units_order() {
+    let mut case = CaseResult::default();
+    let mut record = test_record(1., 1.);
+    record.insert("iterations", 100., "");
+    case.runs.push(record);
+
+    let units = case.units();
+    assert_eq!(units, vec![(WALL_TIME.to_string(), "s".to_string()),
+                           ("max_rss".to_string(), "kB".to_string()),
+                           ("iterations".to_string(), "".to_string())]);
+}
+
+#[test]
+fn test_result_set_samples() {
+    let mut results = ResultSet::new();
+    let mut with_rss = CaseResult::default();
+    with_rss.runs.push(test_record(1., 10.));
+    results.insert("with_rss".to_string(), with_rss);
+
+    let mut wall_only = CaseResult::default();
+    let mut record = RunRecord::default();
+    record.insert(WALL_TIME, 2., "s");
+    wall_only.runs.push(record);
+    results.insert("wall_only".to_string(), wall_only);
+
+    assert_eq!(samples(&results, WALL_TIME).len(), 2);
+    let rss = samples(&results, "max_rss");
+    assert_eq!(rss.len(), 1);
+    assert_eq!(rss["with_rss"], vec![10.]);
+}
+
+#[test]
+fn test_result_set_metrics() {
+    let mut results = ResultSet::new();
+    let mut case = CaseResult::default();
+    case.runs.push(test_record(1., 10.));
+    results.insert("a".to_string(), case);
+    let mut case = CaseResult::default();
+    let mut record = RunRecord::default();
+    record.insert("iterations", 5., "");
+    case.runs.push(record);
+    results.insert("b".to_string(), case);
+
+    assert_eq!(metrics(&results), vec![WALL_TIME, "max_rss", "iterations"]);
+    assert!(metrics(&ResultSet::new()).is_empty());
+}
+
+#[test]
+fn test_without_failures() {
+    let failed = |outcome| RunStatus { outcome, exit_code: None, signal: None, message: None };
+    let mut case = CaseResult::default();
+    case.runs.push(test_record(1., 10.));
+    let mut record = test_record(0.01, 5.);
+    record.status = Some(RunStatus { exit_code: Some(1), ..failed(RunOutcome::Failed) });
+    case.runs.push(record);
+    case.runs.push(RunRecord { status: Some(failed(RunOutcome::TimedOut)),
+                               ..RunRecord::default() });
+    let mut results = ResultSet::new();
+    results.insert("crashy".to_string(), case);
+
+    assert_eq!(failures(&results)["crashy"], 2);
+    assert_eq!(samples(&results, WALL_TIME)["crashy"], vec![1., 0.01]);
+    let successful = without_failures(&results);
+    assert_eq!(samples(&successful, WALL_TIME)["crashy"], vec![1.]);
+    assert_eq!(successful["crashy"].failures(), 0);
+}
+
+#[test]
+fn test_group_by_parameter() {
+    let mut results = ResultSet::new();
+    for &(name, threads) in &[("a_t1", Some("1")), ("b_t1", Some("1")), ("a_t4", Some("4")),
+                              ("plain", None)] {
+        let mut case = CaseResult::default();
+        if let Some(t) = threads {
+            case.parameters.insert("threads".to_string(), t.to_string());
+        }
+        results.insert(name.to_string(), case);
+    }
+
+    let groups = group_by(&results, "threads");
+    assert_eq!(groups.len(), 3);
+    assert_eq!(groups[&Some("1".to_string())].len(), 2);
+    assert!(groups[&Some("4".to_string())].contains_key("a_t4"));
+    assert!(groups[&None].contains_key("plain"));
+}
@@ -0,0 +1,276 @@
+//! Statistical tests whether the samples of two benchmark runs differ significantly.
+
+use stat::{mean, variance};
+use std::f64;
+
+/// Available tests for the significance of a difference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Test {
+    /// Welch's t-test, compares the means without assuming equal variances.
+    Welch,
+    /// Mann-Whitney U test, compares the ranks and does not assume a normal distribution.
+    MannWhitney,
+}
+
+impl Test {
+    pub fn from_name(name: &str) -> Option<Test> {
+        match name {
+            "welch" => Some(Test::Welch),
+            "mann-whitney" => Some(Test::MannWhitney),
+            _ => None,
+        }
+    }
+
+    /// Run the test on the samples of two runs.
+    pub fn run(&self, samples1: &[f64], samples2: &[f64]) -> TestResult {
+        match *self {
+            Test::Welch => welch_t_test(samples1, samples2),
+            Test::MannWhitney => mann_whitney_u(samples1, samples2),
+        }
+    }
+}
+
+/// Outcome of a significance test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// Probability to see a difference at least this large if both runs were the same.
+    pub p_value: f64,
+    /// Size of the difference, positive if the second run has larger values. Cohen's d for
+    /// Welch's t-test, the rank-biserial correlation for Mann-Whitney U.
+    pub effect_size: f64,
+    pub test: Test,
+}
+
+/// Two sided Welch's t-test.
+pub fn welch_t_test(samples1: &[f64], samples2: &[f64]) -> TestResult {
+    let (n1, n2) = (samples1.len() as f64, samples2.len() as f64);
+    let (mean1, mean2) = (mean(samples1), mean(samples2));
+    // a single sample has no variance, it is treated as exact
+    let var1 = if samples1.len() > 1 { variance(samples1) } else { 0. };
+    let var2 = if samples2.len() > 1 { variance(samples2) } else { 0. };
+
+    let pooled_dev = ((var1 + var2) / 2.).sqrt();
+    let effect_size = if pooled_dev > 0. {
+        (mean2 - mean1) / pooled_dev
+    } else if mean1 == mean2 {
+        0.
+    } else {
+        (mean2 - mean1).signum() * f64::INFINITY
+    };
+
+    let (se1, se2) = (var1 / n1, var2 / n2);
+    let p_value = if n1 < 2. || n2 < 2. {
+        // no degrees of freedom to judge the difference
+        1.
+    } else if se1 + se2 == 0. {
+        if mean1 == mean2 { 1. } else { 0. }
+    } else {
+        let t = (mean1 - mean2) / (se1 + se2).sqrt();
+        let df = (se1 + se2).powi(2) / (se1.powi(2) / (n1 - 1.) + se2.powi(2) / (n2 - 1.));
+        incomplete_beta(df / 2., 0.5, df / (df + t * t))
+    };
+
+    TestResult { p_value, effect_size, test: Test::Welch }
+}
+
+/// Two sided Mann-Whitney U test, using the normal approximation with corrections for ties and
+/// continuity.
+pub fn mann_whitney_u(samples1: &[f64], samples2: &[f64]) -> TestResult {
+    let (n1, n2) = (samples1.len() as f64, samples2.len() as f64);
+
+    let mut all: Vec<(f64, usize)> = samples1.iter().map(|&v| (v, 0))
+        .chain(samples2.iter().map(|&v| (v, 1)))
+        .collect();
+    all.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    // average ranks for tied values
+    let mut rank_sum1 = 0.;
+    let mut tie_term = 0.;
+    let mut i = 0;
+    while i < all.len() {
+        let mut j = i;
+        while j + 1 < all.len() && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2. + 1.;
+        let ties = (j - i + 1) as f64;
+        tie_term += ties.powi(3) - ties;
+        rank_sum1 += rank * all[i..j + 1].iter().filter(|v| v.1 == 0).count() as f64;
+        i = j + 1;
+    }
+
+    // number of pairs where the first sample is larger
+    let u1 = rank_sum1 - n1 * (n1 + 1.) / 2.;
+    let n = n1 + n2;
+    let mu = n1 * n2 / 2.;
+    let sigma = (n1 * n2 / 12. * ((n + 1.) - tie_term / (n * (n - 1.)))).sqrt();
+
+    let p_value = if sigma > 0. {
+        let z = ((u1 - mu).abs() - 0.5).max(0.) / sigma;
+        erfc(z / f64::consts::SQRT_2).min(1.)
+    } else {
+        1.
+    };
+
+    TestResult {
+        p_value,
+        effect_size: 1. - 2. * u1 / (n1 * n2),
+        test: Test::MannWhitney,
+    }
+}
+
+/// Critical value of Student's t-distribution with `df` degrees of freedom, the absolute t is
+/// larger with probability `alpha`.
+pub fn t_critical(alpha: f64, df: f64) -> f64 {
+    // the two sided tail probability falls with t, bisect until it is alpha
+    let (mut low, mut high) = (0., 1e4);
+    for _ in 0..100 {
+        let t = (low + high) / 2.;
+        if incomplete_beta(df / 2., 0.5, df / (df + t * t)) > alpha {
+            low = t;
+        } else {
+            high = t;
+        }
+    }
+    (low + high) / 2.
+}
+
+/// Regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    if x >= 1. {
+        return 1.;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b)
+                 + a * x.ln() + b * (1. - x).ln()).exp();
+    // the continued fraction converges quickly only on one side
+    if x < (a + 1.) / (a + b + 2.) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1. - front * beta_fraction(b, a, 1. - x) / b
+    }
+}
+
+/// Continued fraction of the incomplete beta function, evaluated with Lentz's method.
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.;
+    let mut d = 1. - (a + b) * x / (a + 1.);
+    if d.abs() < TINY { d = TINY; }
+    d = 1. / d;
+    let mut h = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2. * m;
+
+        let aa = m * (b - m) * x / ((a + m2 - 1.) * (a + m2));
+        d = 1. + aa * d;
+        if d.abs() < TINY { d = TINY; }
+        c = 1. + aa / c;
+        if c.abs() < TINY { c = TINY; }
+        d = 1. / d;
+        h *= d * c;
+
+        let aa = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.));
+        d = 1. + aa * d;
+        if d.abs() < TINY { d = TINY; }
+        c = 1. + aa / c;
+        if c.abs() < TINY { c = TINY; }
+        d = 1. / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// Logarithm of the gamma function, Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [76.18009172947146, -86.50532032941677, 24.01409824083091,
+                                    -1.231739572450155, 0.1208650973866179e-2,
+                                    -0.5395239384953e-5];
+    let mut y = x;
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for c in &COEFFICIENTS {
+        y += 1.;
+        series += c / y;
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Complementary error function, accurate to about 1e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1. / (1. + 0.5 * z);
+    let result = t * (-z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196
+        + t * (0.09678418 + t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398
+        + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))))).exp();
+    if x >= 0. { result } else { 2. - result }
+}
+
+
+// --------------------- tests for the significance tests ---------------------------
+
+#[test]
+fn test_special_functions() {
+    assert!((ln_gamma(5.) - 24f64.ln()).abs() < 1e-10);
+    assert!((erfc(0.) - 1.).abs() < 1e-6);
+    assert!((erfc(1.) - 0.157299).abs() < 1e-6);
+    assert!((incomplete_beta(2., 3., 0.4) - 0.5248).abs() < 1e-10);
+}
+
+#[test]
+fn test_t_critical() {
+    // reference values from scipy.stats.t.ppf(0.975, df)
+    assert!((t_critical(0.05, 1.) - 12.7062).abs() < 1e-3);
+    assert!((t_critical(0.05, 9.) - 2.2622).abs() < 1e-3);
+    assert!((t_critical(0.01, 30.) - 2.7500).abs() < 1e-3);
+}
+
+#[test]
+fn test_welch() {
+    // reference values from scipy.stats.ttest_ind(equal_var=False)
+    let result = welch_t_test(&[1., 2., 3., 4., 5.], &[2., 3., 4., 5., 6.]);
+    assert!((result.p_value - 0.346594).abs() < 1e-5);
+    assert!((result.effect_size - 0.632456).abs() < 1e-5);
+
+    let result = welch_t_test(&[10., 10.1, 9.9, 10.05], &[12., 12.2, 11.9, 12.1, 11.8]);
+    assert!(result.p_value < 1e-5);
+    assert!(result.effect_size > 0.);
+}
+
+#[test]
+fn test_welch_without_variance() {
+    assert_eq!(welch_t_test(&[1., 1.], &[1., 1., 1.]).p_value, 1.);
+    assert_eq!(welch_t_test(&[1., 1.], &[2., 2.]).p_value, 0.);
+    assert_eq!(welch_t_test(&[1.], &[2., 3.]).p_value, 1.);
+    assert_eq!(welch_t_test(&[1.], &[2.]).p_value, 1.);
+}
+
+#[test]
+fn test_mann_whitney() {
+    // reference values from scipy.stats.mannwhitneyu(method="asymptotic")
+    let result = mann_whitney_u(&[1., 2., 3., 4., 5.], &[6., 7., 8., 9., 10.]);
+    assert!((result.p_value - 0.012186).abs() < 1e-5);
+    assert_eq!(result.effect_size, 1.);
+
+    let result = mann_whitney_u(&[1., 2., 2., 3.], &[2., 3., 3., 4.]);
+    assert!(result.p_value > 0.05);
+
+    assert_eq!(mann_whitney_u(&[1., 1.], &[1., 1.]).p_value, 1.);
+}
+
+#[test]
+fn test_from_name() {
+    assert_eq!(Test::from_name("welch"), Some(Test::Welch));
+    assert_eq!(Test::from_name("mann-whitney"), Some(Test::MannWhitney));
+    assert_eq!(Test::from_name("chi"), None);
+}
@@ -1,14 +1,25 @@
-/// Calculate statistics from the measured durations
+//! Calculate statistics from the measured durations
 
 extern crate stat;
-use stat::{mean, minmax, absdev};
+use stat::{mean, minmax, absdev, sd, median_from_sorted_data, quantile_from_sorted_data};
 use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
 
-use config::file_to_yaml;
-use yaml_rust::Yaml;
+use import;
+use json;
+use messages;
+use significance;
+use significance::{Test, TestResult};
+use random::Rng;
+use affinity;
+use benchmarking::RunOrder;
+use results::{ResultSet, CaseResult, RunRecord, RunOutcome, RunStatus, WALL_TIME, default_unit};
+use metadata::{Metadata, FORMAT_VERSION};
+use yaml_rust::{Yaml, YamlLoader};
 
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct BMStatistics {
     /// average runtime of the benchmark
     pub avg: f64,
@@ -16,19 +27,107 @@ pub struct BMStatistics {
     pub min: f64,
     /// longest runtime of all runs
     pub max: f64,
-    /// std deviation of the results, asuming a normal distribution of the runtimes
+    /// sample standard deviation of the runtimes
     pub dev: f64,
+    /// mean absolute deviation from the average
+    pub absdev: f64,
     /// number of measurements
     pub count: usize,
+    /// median runtime of all runs
+    pub median: f64,
+    /// percentiles of the runtimes
+    pub p5: f64,
+    pub p25: f64,
+    pub p75: f64,
+    pub p95: f64,
+    pub p99: f64,
+    /// interquartile range, p75 - p25
+    pub iqr: f64,
+    /// bootstrap confidence interval of the average, if the analysis draws resamples
+    pub avg_ci: Option<(f64, f64)>,
+    /// bootstrap confidence interval of the median, if the analysis draws resamples
+    pub median_ci: Option<(f64, f64)>,
+    /// number of mild and severe outliers in the measurements
+    pub mild_outliers: usize,
+    pub severe_outliers: usize,
+}
+
+/// Settings for the statistical analysis of the measurements.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// confidence level in percent
+    pub confidence: f64,
+    /// number of resamples drawn from the measurements, 0 calculates no intervals
+    pub resamples: usize,
+    /// seed for the resampling, the same seed gives the same intervals
+    pub seed: u64,
+    /// how outliers are detected
+    pub outliers: OutlierMethod,
+    /// calculate the statistics without the outliers
+    pub exclude_outliers: bool,
+    /// calculate the statistics with the runs that did not succeed
+    pub include_failed: bool,
+}
+
+impl Default for Analysis {
+    fn default() -> Analysis {
+        Analysis {
+            confidence: 95.,
+            resamples: 1000,
+            seed: 42,
+            outliers: OutlierMethod::Tukey,
+            exclude_outliers: false,
+            include_failed: false,
+        }
+    }
+}
+
+impl Analysis {
+    /// Default settings for statistics that are never shown with intervals, the bootstrap is
+    /// skipped.
+    pub fn without_intervals() -> Analysis {
+        Analysis { resamples: 0, ..Analysis::default() }
+    }
+}
+
+/// Methods to detect outliers in the measurements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlierMethod {
+    /// Tukey's fences, mild outliers are more than 1.5 IQR outside the quartiles, severe ones
+    /// more than 3 IQR.
+    Tukey,
+    /// Modified z-score based on the median absolute deviation, mild outliers have a score above
+    /// 3.5, severe ones above 7.
+    Mad,
+}
+
+impl OutlierMethod {
+    pub fn from_name(name: &str) -> Option<OutlierMethod> {
+        match name {
+            "tukey" => Some(OutlierMethod::Tukey),
+            "mad" => Some(OutlierMethod::Mad),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outlier {
+    No,
+    Mild,
+    Severe,
 }
 
 pub struct ComparisonResult {
     pub avg: Comparison,
     pub min: Comparison,
     pub max: Comparison,
+    /// outcome of the significance test on the samples, if one was run
+    pub significance: Option<TestResult>,
 }
 
 /// Compare two benchmark runs against each other. This holds the relevant information who won.
+#[derive(Debug, PartialEq)]
 pub enum Comparison {
     Equal,
     OneIsFaster,
@@ -36,28 +135,206 @@ pub enum Comparison {
 }
 
 
+/// Winners of every statistic when comparing the same case in several result sets. The winners
+/// are indices into the compared sets, all sets within the tolerance of the best value win.
+#[derive(Debug, Default)]
+pub struct Ranking {
+    pub avg: Vec<usize>,
+    pub min: Vec<usize>,
+    pub max: Vec<usize>,
+    pub dev: Vec<usize>,
+    /// number of sets that contain the case
+    pub participants: usize,
+}
+
+/// Number of won statistics for one result set.
+#[derive(Debug, Default, PartialEq)]
+pub struct Wins {
+    pub avg: usize,
+    pub min: usize,
+    pub max: usize,
+    pub dev: usize,
+}
+
+impl Wins {
+    pub fn total(&self) -> usize {
+        self.avg + self.min + self.max + self.dev
+    }
+}
 
 /// Postprocess the results of all benchmark runs. Currently only prints a table with most
 /// interesting information.
-pub fn process_results(run_statistic: &BTreeMap<String, Vec<f32>>)
+pub fn process_results(run_statistic: &BTreeMap<String, Vec<f64>>, analysis: &Analysis)
     -> BTreeMap<String, BMStatistics> {
         let mut result = BTreeMap::new();
 
         for (bm_name, times) in run_statistic {
-            assert!(times.len() > 0);
-            let (min, _, max, _) = minmax(times);
-            result.insert(bm_name.clone(),
-            BMStatistics {
-                avg: mean(times),
-                min: min,
-                max: max,
-                dev: absdev(times),
-                count: times.len(),
-            });
+            result.insert(bm_name.clone(), calc_statistics(times, analysis));
         }
         result
     }
 
+/// Postprocess every metric of all benchmark runs, mapped by case and metric name.
+pub fn process_metrics(results: &ResultSet) -> BTreeMap<String, BTreeMap<String, BMStatistics>> {
+    let mut result = BTreeMap::new();
+
+    for (bm_name, case) in results {
+        let mut metrics = BTreeMap::new();
+        for (metric, _) in case.units() {
+            metrics.insert(metric.clone(), calc_statistics(&case.samples(&metric),
+                                                           &Analysis::without_intervals()));
+        }
+        // runs that did not exit measured nothing
+        if !metrics.is_empty() {
+            result.insert(bm_name.clone(), metrics);
+        }
+    }
+    result
+}
+
+fn calc_statistics(values: &[f64], analysis: &Analysis) -> BMStatistics {
+    assert!(!values.is_empty());
+    let outliers = classify_outliers(values, analysis.outliers);
+    let mild_outliers = outliers.iter().filter(|&&o| o == Outlier::Mild).count();
+    let severe_outliers = outliers.iter().filter(|&&o| o == Outlier::Severe).count();
+
+    let kept: Vec<f64>;
+    let values = if analysis.exclude_outliers {
+        kept = without_outliers(values, analysis.outliers);
+        &kept
+    } else {
+        values
+    };
+
+    let (min, _, max, _) = minmax(values);
+    let (avg_ci, median_ci) = if analysis.resamples > 0 {
+        let (avg_ci, median_ci) = bootstrap_intervals(values, analysis);
+        (Some(avg_ci), Some(median_ci))
+    } else {
+        (None, None)
+    };
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let percentile = |p: f64| quantile_from_sorted_data(&sorted, p / 100.);
+
+    BMStatistics {
+        avg: mean(values),
+        min,
+        max,
+        // a single measurement does not deviate
+        dev: if values.len() > 1 { sd(values) } else { 0. },
+        absdev: absdev(values),
+        count: values.len(),
+        median: median_from_sorted_data(&sorted),
+        p5: percentile(5.),
+        p25: percentile(25.),
+        p75: percentile(75.),
+        p95: percentile(95.),
+        p99: percentile(99.),
+        iqr: percentile(75.) - percentile(25.),
+        avg_ci,
+        median_ci,
+        mild_outliers,
+        severe_outliers,
+    }
+}
+
+/// Relative half width of the 95% confidence interval of the average in percent, infinite if
+/// there are too few measurements to judge. Adaptive cases decide with it after every batch, so
+/// the interval comes from Student's t-distribution instead of the bootstrap.
+pub fn relative_ci(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return f64::INFINITY;
+    }
+    let n = values.len() as f64;
+    let avg = mean(values);
+    let half_width = significance::t_critical(0.05, n - 1.) * sd(values) / n.sqrt();
+    if avg == 0. {
+        return if half_width == 0. { 0. } else { f64::INFINITY };
+    }
+    100. * half_width / avg.abs()
+}
+
+/// Classify every measurement as outlier or not. The raw measurements are never changed, only
+/// the statistics may leave outliers out. Without any spread of the measurements there is no
+/// scale to judge a distance with, so nothing is an outlier.
+pub fn classify_outliers(values: &[f64], method: OutlierMethod) -> Vec<Outlier> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let score: Box<dyn Fn(f64) -> f64> = match method {
+        OutlierMethod::Tukey => {
+            let q1 = quantile_from_sorted_data(&sorted, 0.25);
+            let q3 = quantile_from_sorted_data(&sorted, 0.75);
+            let iqr = q3 - q1;
+            if iqr == 0. {
+                return vec![Outlier::No; values.len()];
+            }
+            // distance outside the quartiles in multiples of 1.5 IQR
+            Box::new(move |v| (q1 - v).max(v - q3).max(0.) / (1.5 * iqr))
+        }
+        OutlierMethod::Mad => {
+            let med = median_from_sorted_data(&sorted);
+            let mad = median(&sorted.iter().map(|v| (v - med).abs()).collect::<Vec<_>>());
+            if mad == 0. {
+                return vec![Outlier::No; values.len()];
+            }
+            // modified z-score in multiples of the 3.5 threshold
+            Box::new(move |v| 0.6745 * (v - med).abs() / mad / 3.5)
+        }
+    };
+
+    values.iter().map(|&v| {
+        let s = score(v);
+        if s > 2. {
+            Outlier::Severe
+        } else if s > 1. {
+            Outlier::Mild
+        } else {
+            Outlier::No
+        }
+    }).collect()
+}
+
+/// The measurements that are no outliers for `method`.
+pub fn without_outliers(values: &[f64], method: OutlierMethod) -> Vec<f64> {
+    values.iter().zip(classify_outliers(values, method))
+        .filter(|&(_, o)| o == Outlier::No)
+        .map(|(&v, _)| v)
+        .collect()
+}
+
+pub fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    median_from_sorted_data(&sorted)
+}
+
+/// Percentile bootstrap confidence intervals for the average and the median.
+fn bootstrap_intervals(values: &[f64], analysis: &Analysis) -> ((f64, f64), (f64, f64)) {
+    let mut rng = Rng::new(analysis.seed);
+    let mut avgs = Vec::with_capacity(analysis.resamples);
+    let mut medians = Vec::with_capacity(analysis.resamples);
+    let mut resample = vec![0.; values.len()];
+
+    for _ in 0..analysis.resamples {
+        for value in resample.iter_mut() {
+            *value = values[rng.below(values.len())];
+        }
+        avgs.push(mean(&resample));
+        medians.push(median(&resample));
+    }
+
+    let tail = (1. - analysis.confidence / 100.) / 2.;
+    let interval = |estimates: &mut Vec<f64>| {
+        estimates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        (quantile_from_sorted_data(estimates, tail),
+         quantile_from_sorted_data(estimates, 1. - tail))
+    };
+    (interval(&mut avgs), interval(&mut medians))
+}
+
 /// Compare two runs of the same benchmark against each other and store which one one (with the
 /// given percantage of tolerance for equality).
 pub fn compare_runs(run1: &BTreeMap<String, BMStatistics>,
@@ -81,55 +358,328 @@ pub fn compare_runs(run1: &BTreeMap<String, BMStatistics>,
                 avg: best_avg,
                 min: best_min,
                 max: best_max,
+                significance: None,
+            });
+        }
+        result
+    }
+
+/// Run a significance test on the samples of every compared case. Differences that are not
+/// significant on the level `alpha` are considered equal, no matter how large they are.
+pub fn test_significance(comparison: &mut BTreeMap<String, ComparisonResult>,
+                         samples1: &BTreeMap<String, Vec<f64>>,
+                         samples2: &BTreeMap<String, Vec<f64>>,
+                         test: Test, alpha: f64) {
+    for (bm_name, cmp) in comparison.iter_mut() {
+        let (s1, s2) = match (samples1.get(bm_name), samples2.get(bm_name)) {
+            (Some(s1), Some(s2)) => (s1, s2),
+            _ => continue,
+        };
+        let result = test.run(s1, s2);
+
+        if result.p_value >= alpha {
+            cmp.avg = Comparison::Equal;
+            cmp.min = Comparison::Equal;
+            cmp.max = Comparison::Equal;
+        }
+        cmp.significance = Some(result);
+    }
+}
+
+/// Find the winners of every case that is in at least two of the result sets (with the given
+/// percentage of tolerance for equality).
+pub fn rank_runs(runs: &[BTreeMap<String, BMStatistics>], tolerance: f64)
+    -> BTreeMap<String, Ranking> {
+        let mut result = BTreeMap::new();
+        let mut names: Vec<&String> = runs.iter().flat_map(|r| r.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        for bm_name in names {
+            let stats: Vec<(usize, &BMStatistics)> = runs.iter().enumerate()
+                .filter_map(|(i, run)| run.get(bm_name).map(|s| (i, s)))
+                .collect();
+            if stats.len() < 2 {
+                continue;
+            }
+            let best = |value: &dyn Fn(&BMStatistics) -> f64| {
+                let values: Vec<(usize, f64)> = stats.iter().map(|&(i, s)| (i, value(s))).collect();
+                best_of(&values, tolerance)
+            };
+
+            result.insert(bm_name.to_string(),
+            Ranking {
+                avg: best(&|s| s.avg),
+                min: best(&|s| s.min),
+                max: best(&|s| s.max),
+                dev: best(&calc_relative_variance),
+                participants: stats.len(),
             });
         }
         result
     }
 
+/// Count the won statistics of each of `n_runs` result sets. A statistic where every
+/// participant is equal has no winner.
+pub fn count_wins(rankings: &BTreeMap<String, Ranking>, n_runs: usize) -> Vec<Wins> {
+    let mut wins: Vec<Wins> = (0..n_runs).map(|_| Wins::default()).collect();
+    let decided = |winners: &Vec<usize>, participants: usize| {
+        if winners.len() < participants { winners.clone() } else { Vec::new() }
+    };
+
+    for ranking in rankings.values() {
+        for i in decided(&ranking.avg, ranking.participants) { wins[i].avg += 1; }
+        for i in decided(&ranking.min, ranking.participants) { wins[i].min += 1; }
+        for i in decided(&ranking.max, ranking.participants) { wins[i].max += 1; }
+        for i in decided(&ranking.dev, ranking.participants) { wins[i].dev += 1; }
+    }
+    wins
+}
+
 /// Calculate the procentual variance for that case. 100. * stddev / avg
 pub fn calc_relative_variance(statistics: &BMStatistics) -> f64 {
     100. * statistics.dev / statistics.avg
 }
 
-/// Read in a result file and return all execution times mapped to their command name.
+/// Read in a result file and return all measured runs mapped to their command name.
 /// Panics if the file is not existing or the yaml cant be loaded.
-pub fn read_result_from_file(file_name: &str) -> BTreeMap<String, Vec<f32>> {
-    let yml = file_to_yaml(file_name);
-    let yml = &yml[0];
-    results_from_yaml(&yml)
+pub fn read_result_from_file(file_name: &str) -> ResultSet {
+    read_results_with_metadata(file_name).0
+}
+
+/// Read in a result file together with the metadata of its session, older files have none.
+/// Files in JSON have the same layout as the Yaml ones. Results of hyperfine and criterion are
+/// imported, a directory is read as the output directory of criterion. They have no metadata.
+pub fn read_results_with_metadata(file_name: &str) -> (ResultSet, Option<Metadata>) {
+    if Path::new(file_name).is_dir() {
+        return (import::from_criterion_directory(file_name), None);
+    }
+    let doc = result_file_to_yaml(file_name);
+    match import::from_other_tool(file_name, &doc) {
+        Some(results) => (results, None),
+        None => results_from_yaml(&doc),
+    }
+}
+
+/// Load a result file either as JSON or as Yaml.
+fn result_file_to_yaml(file_name: &str) -> Yaml {
+    let content = match fs::read_to_string(file_name) {
+        Ok(content) => content,
+        Err(e) => {
+            messages::invalid_filename(file_name);
+            panic!("{:?}", e);
+        }
+    };
+    if json::is_json(file_name, &content) {
+        match json::parse(&content) {
+            Ok(doc) => doc,
+            Err(e) => {
+                messages::invalid_json(file_name);
+                panic!("{}", e);
+            }
+        }
+    } else {
+        match YamlLoader::load_from_str(&content) {
+            Ok(mut docs) => docs.swap_remove(0),
+            Err(e) => {
+                messages::invalid_yaml(file_name);
+                panic!("{:?}", e);
+            }
+        }
+    }
+}
+
+/// Current files are a hash with the `format_version`, the `metadata` and the list of `cases`.
+/// Older files are only the list of cases.
+fn results_from_yaml(doc: &Yaml) -> (ResultSet, Option<Metadata>) {
+    if doc.as_hash().is_none() {
+        return (cases_from_yaml(doc), None);
+    }
+
+    let version = doc["format_version"].as_i64().expect("Result file without format_version!");
+    if version > FORMAT_VERSION {
+        panic!("Result file has format version {}, this macrobm only reads up to {}!",
+               version, FORMAT_VERSION);
+    }
+    // exports of files without metadata have none
+    let metadata = match doc["metadata"] {
+        Yaml::BadValue => None,
+        ref metadata => Some(metadata_from_yaml(metadata)),
+    };
+    let mut result = cases_from_yaml(&doc["cases"]);
+    for case in result.values_mut() {
+        case.order = case.order.or(metadata.as_ref().and_then(|m| m.order));
+    }
+    (result, metadata)
+}
+
+pub fn metadata_from_yaml(doc: &Yaml) -> Metadata {
+    let string = |key: &str| doc[key].as_str().unwrap_or("").to_string();
+    let seed = doc["seed"].as_i64().unwrap_or(0) as u64;
+    Metadata {
+        macrobm_version: string("macrobm_version"),
+        timestamp: string("timestamp"),
+        hostname: string("hostname"),
+        kernel: string("kernel"),
+        cpu_model: string("cpu_model"),
+        cores: doc["cores"].as_i64().unwrap_or(0),
+        jobs: doc["jobs"].as_i64().unwrap_or(0),
+        config_file: string("config_file"),
+        git_commit: doc["git_commit"].as_str().map(str::to_string),
+        order: doc["order"].as_str().and_then(|name| RunOrder::from_name(name, seed)),
+    }
 }
 
-fn results_from_yaml(doc: &Yaml) -> BTreeMap<String, Vec<f32>> {
+/// Every case is a hash with the `runs` and their metrics. Older files store a plain list of
+/// times or a hash with one list of values per metric and are still understood.
+fn cases_from_yaml(doc: &Yaml) -> ResultSet {
     let mut result = BTreeMap::new();
 
     for single_result in doc.as_vec().unwrap() {
         let single_result = single_result.as_hash().unwrap();
 
-        for (name, times) in single_result {
-            let mut times_float = Vec::new();
+        for (name, case_data) in single_result {
+            let case = match *case_data {
+                Yaml::Array(ref times) => case_from_value_lists(&[(WALL_TIME, times)]),
+                Yaml::Hash(_) if !case_data["runs"].is_badvalue() => case_from_runs(case_data),
+                Yaml::Hash(ref hash) => {
+                    let mut lists = Vec::new();
+                    for (metric, values) in hash {
+                        let metric = match metric.as_str().unwrap() {
+                            "times" => WALL_TIME,
+                            m => m,
+                        };
+                        if let Some(values) = values.as_vec() {
+                            lists.push((metric, values));
+                        }
+                    }
+                    let mut case = case_from_value_lists(&lists);
+                    case.warmup = case_data["warmup"].as_i64().unwrap_or(0);
+                    case
+                }
+                _ => panic!("Invalid result for {:?}", name),
+            };
+            result.insert(name.as_str().unwrap().to_string(), case);
+        }
+    }
+    result
+}
+
+/// Read a case that stores a list of `runs`, each with a value per metric.
+fn case_from_runs(case_data: &Yaml) -> CaseResult {
+    let units = case_data["units"].as_hash();
+    let unit_of = |metric: &str| {
+        units.and_then(|u| u.get(&Yaml::String(metric.to_string())))
+            .and_then(|u| u.as_str())
+            .unwrap_or_else(|| default_unit(metric))
+            .to_string()
+    };
+
+    let seed = case_data["seed"].as_i64().unwrap_or(0) as u64;
+    let mut case = CaseResult {
+        warmup: case_data["warmup"].as_i64().unwrap_or(0),
+        order: case_data["order"].as_str().and_then(|name| RunOrder::from_name(name, seed)),
+        shell_startup: case_data["shell_startup"].as_f64(),
+        config: match case_data["config"] {
+            Yaml::BadValue => None,
+            ref config => Some(config.clone()),
+        },
+        ..CaseResult::default()
+    };
+    if let Some(parameters) = case_data["parameters"].as_hash() {
+        for (param, value) in parameters {
+            case.parameters.insert(param.as_str().unwrap().to_string(),
+                                   value.as_str().unwrap().to_string());
+        }
+    }
+
+    for run in case_data["runs"].as_vec().unwrap() {
+        case.runs.push(run_from_yaml(run, unit_of));
+    }
+    case
+}
+
+/// Read a run with a value per metric, `unit_of` gives the unit of every metric.
+pub fn run_from_yaml<F: Fn(&str) -> String>(run: &Yaml, unit_of: F) -> RunRecord {
+    let mut record = RunRecord {
+        status: run_status_from_yaml(run),
+        ..RunRecord::default()
+    };
+    for (metric, value) in run.as_hash().unwrap() {
+        match metric.as_str().unwrap() {
+            // the CPUs a run was pinned to and how it ended are no metrics
+            "cpus" => {
+                record.cpus = value.as_str().and_then(affinity::parse_cpu_list)
+                    .unwrap_or_default();
+            }
+            "outcome" | "exit_code" | "signal" | "error" => (),
+            metric => record.insert(metric, yaml_number(value), &unit_of(metric)),
+        }
+    }
+    record
+}
+
+/// How a run ended, None for files that do not know it.
+fn run_status_from_yaml(run: &Yaml) -> Option<RunStatus> {
+    let name = run["outcome"].as_str()?;
+    let outcome = match RunOutcome::from_name(name) {
+        Some(outcome) => outcome,
+        None => panic!("Unknown outcome {} of a run!", name),
+    };
+    Some(RunStatus {
+        outcome,
+        exit_code: run["exit_code"].as_i64().map(|code| code as i32),
+        signal: run["signal"].as_i64().map(|signal| signal as i32),
+        message: run["error"].as_str().map(str::to_string),
+    })
+}
 
-            for el in times.as_vec().unwrap() {
-                times_float.push(el.as_f64().unwrap() as f32);
+/// Build the runs of a case from one list of values for every metric, like older result files
+/// store them. The n-th value of every list belongs to the n-th run.
+fn case_from_value_lists(lists: &[(&str, &Vec<Yaml>)]) -> CaseResult {
+    let mut case = CaseResult::default();
+
+    for &(metric, values) in lists {
+        for (i, value) in values.iter().enumerate() {
+            if case.runs.len() <= i {
+                case.runs.push(RunRecord::default());
             }
-            result.insert(name.as_str().unwrap().to_string(), times_float);
+            case.runs[i].insert(metric, yaml_number(value), default_unit(metric));
         }
     }
-    result
+    case
+}
+
+/// Integers and reals are both valid numbers in the result files.
+pub fn yaml_number(value: &Yaml) -> f64 {
+    match *value {
+        Yaml::Integer(i) => i as f64,
+        _ => value.as_f64().unwrap(),
+    }
 }
 
 /// Compare two metrics for equality, tol(0. - 100.) is given in percent!
 fn compare_single(value1: f64, value2: f64, tol: f64) -> Comparison {
-    assert!(value1 != 0.);
-    if (value1 - value2).abs() / value1.abs() <= (tol / 100.) {
-        return Comparison::Equal;
+    // counting metrics like page faults are often zero
+    if value1 == value2 || (value1 != 0. && (value1 - value2).abs() / value1.abs() <= (tol / 100.)) {
+        Comparison::Equal
     } else if value1 < value2 {
-        return Comparison::OneIsFaster;
+        Comparison::OneIsFaster
     } else {
-        return Comparison::TwoIsFaster;
+        Comparison::TwoIsFaster
     }
 }
 
 
+/// Indices of all values that are equal to the smallest one, within the tolerance.
+fn best_of(values: &[(usize, f64)], tol: f64) -> Vec<usize> {
+    let best = values.iter().map(|&(_, v)| v).fold(f64::INFINITY, f64::min);
+    values.iter()
+        .filter(|&&(_, v)| compare_single(best, v, tol) == Comparison::Equal)
+        .map(|&(i, _)| i)
+        .collect()
+}
+
 
 // ------------------------------- All tests for the statistic functionality -------------------
 
@@ -137,15 +687,16 @@ fn compare_single(value1: f64, value2: f64, tol: f64) -> Comparison {
 fn test_process_results() {
     let mut collected_times = BTreeMap::new();
     collected_times.insert("simulation".to_string(), vec![15., 14., 16.]);
-    let stats = process_results(&collected_times);
+    let stats = process_results(&collected_times, &Analysis::default());
     let stats = stats.get("simulation").unwrap();
 
     assert_eq!(stats.avg, 15., "Avg is wrong");
     assert_eq!(stats.min, 14., "Min is wrong");
     assert_eq!(stats.max, 16., "Max is wrong");
-    match compare_single(stats.dev, 0.6666, 0.5) {
+    assert_eq!(stats.dev, 1., "Dev is wrong");
+    match compare_single(stats.absdev, 0.6666, 0.5) {
         Comparison::Equal => (),
-        _ => panic!("Dev is wrong"),
+        _ => panic!("Absdev is wrong"),
     };
     assert_eq!(stats.count, 3, "Count is wrong");
 }
@@ -154,8 +705,8 @@ fn test_process_results() {
 #[should_panic(expected = "assertion failed")]
 fn test_process_results_invalid() {
     let mut collected_times = BTreeMap::new();
-    collected_times.insert("simulation".to_string(), Vec::<f32>::new());
-    process_results(&collected_times);
+    collected_times.insert("simulation".to_string(), Vec::<f64>::new());
+    process_results(&collected_times, &Analysis::default());
 }
 
 #[test]
@@ -170,6 +721,7 @@ fn test_compare_runs() {
             max: 15.5,
             dev: 0.005,
             count: 100,
+            ..Default::default()
         });
         x.insert("not_in_other".to_string(),
         BMStatistics {
@@ -178,6 +730,7 @@ fn test_compare_runs() {
             max: 1.6,
             dev: 0.2,
             count: 3,
+            ..Default::default()
         });
         x
     };
@@ -190,6 +743,7 @@ fn test_compare_runs() {
             max: 16.5,
             dev: 0.005,
             count: 100,
+            ..Default::default()
         });
         x.insert("some_unused".to_string(),
         BMStatistics {
@@ -198,6 +752,7 @@ fn test_compare_runs() {
             max: 1.6,
             dev: 0.2,
             count: 3,
+            ..Default::default()
         });
         x
     };
@@ -217,14 +772,8 @@ fn test_compare_runs() {
         Comparison::OneIsFaster => (),
         _ => panic!("one is faster!"),
     }
-    match cmp.get("not_in_other") {
-        Some(_) => panic!("Not allowed in the result!"),
-        None => (),
-    }
-    match cmp.get("some_unused") {
-        Some(_) => panic!("Not allowed in the result!"),
-        None => (),
-    }
+    assert!(!cmp.contains_key("not_in_other"), "Not allowed in the result!");
+    assert!(!cmp.contains_key("some_unused"), "Not allowed in the result!");
 
     // same as previous comparison, but swapped
     let cmp = compare_runs(&run2, &run1, 2.);
@@ -242,14 +791,8 @@ fn test_compare_runs() {
         Comparison::TwoIsFaster => (),
         _ => panic!("two is faster!"),
     }
-    match cmp.get("not_in_other") {
-        Some(_) => panic!("Not allowed in the result!"),
-        None => (),
-    }
-    match cmp.get("some_unused") {
-        Some(_) => panic!("Not allowed in the result!"),
-        None => (),
-    }
+    assert!(!cmp.contains_key("not_in_other"), "Not allowed in the result!");
+    assert!(!cmp.contains_key("some_unused"), "Not allowed in the result!");
 
     // same as previous comparison, but equality because high tolerance
     let cmp = compare_runs(&run2, &run1, 50.);
@@ -267,14 +810,30 @@ fn test_compare_runs() {
         Comparison::Equal => (),
         _ => panic!("result considered equal!"),
     }
-    match cmp.get("not_in_other") {
-        Some(_) => panic!("Not allowed in the result!"),
-        None => (),
-    }
-    match cmp.get("some_unused") {
-        Some(_) => panic!("Not allowed in the result!"),
-        None => (),
-    }
+    assert!(!cmp.contains_key("not_in_other"), "Not allowed in the result!");
+    assert!(!cmp.contains_key("some_unused"), "Not allowed in the result!");
+}
+
+#[test]
+fn test_significance_overrides_tolerance() {
+    let mut samples1 = BTreeMap::new();
+    let mut samples2 = BTreeMap::new();
+    // noisy case, 10% apart but not significant
+    samples1.insert("noisy".to_string(), vec![1.0, 2.0, 1.5, 0.8, 1.2]);
+    samples2.insert("noisy".to_string(), vec![1.1, 2.1, 1.6, 0.9, 1.5]);
+    // stable case, clearly different
+    samples1.insert("stable".to_string(), vec![1.0, 1.01, 0.99, 1.0, 1.02]);
+    samples2.insert("stable".to_string(), vec![1.2, 1.21, 1.19, 1.2, 1.22]);
+
+    let mut cmp = compare_runs(&process_results(&samples1, &Analysis::default()),
+                           &process_results(&samples2, &Analysis::default()), 2.);
+    assert_eq!(cmp["noisy"].avg, Comparison::OneIsFaster);
+
+    test_significance(&mut cmp, &samples1, &samples2, Test::Welch, 0.05);
+    assert_eq!(cmp["noisy"].avg, Comparison::Equal);
+    assert!(cmp["noisy"].significance.as_ref().unwrap().p_value > 0.05);
+    assert_eq!(cmp["stable"].avg, Comparison::OneIsFaster);
+    assert!(cmp["stable"].significance.as_ref().unwrap().p_value < 0.05);
 }
 
 #[test]
@@ -303,6 +862,175 @@ fn test_compare_single() {
         Comparison::Equal => (),
         _ => panic!("Expected equality!"),
     }
+
+    match compare_single(0., 0., 1.) {
+        Comparison::Equal => (),
+        _ => panic!("Expected equality!"),
+    }
+
+    match compare_single(0., 3., 1.) {
+        Comparison::OneIsFaster => (),
+        _ => panic!("Expected one is faster!"),
+    }
+}
+
+#[test]
+fn test_best_of() {
+    assert_eq!(best_of(&[(0, 10.), (1, 9.), (2, 12.)], 0.), vec![1]);
+    assert_eq!(best_of(&[(0, 10.), (1, 9.9), (2, 12.)], 2.), vec![0, 1]);
+    assert_eq!(best_of(&[(0, 0.), (1, 3.), (2, 0.)], 1.), vec![0, 2]);
+}
+
+#[allow(dead_code)]
+fn test_run(avg: f64, dev: f64) -> BTreeMap<String, BMStatistics> {
+    let mut x = BTreeMap::new();
+    x.insert("sleep".to_string(),
+    BMStatistics {
+        avg,
+        min: avg - 1.,
+        max: avg + 1.,
+        dev,
+        count: 10,
+        ..Default::default()
+    });
+    x
+}
+
+#[test]
+fn test_rank_runs() {
+    let mut only_in_one = test_run(1., 0.1);
+    only_in_one.insert("single".to_string(), calc_statistics(&[1., 2.], &Analysis::default()));
+    let runs = vec![test_run(20., 0.1), only_in_one, test_run(10., 0.5), test_run(10.1, 1.)];
+
+    let ranking = rank_runs(&runs, 2.);
+    assert!(!ranking.contains_key("single"), "Cases in a single set have no winner!");
+
+    let sleep = &ranking["sleep"];
+    assert_eq!(sleep.participants, 4);
+    assert_eq!(sleep.avg, vec![1]);
+    assert_eq!(sleep.dev, vec![0]);
+
+    let ranking = rank_runs(&runs[2..], 2.);
+    assert_eq!(ranking["sleep"].avg, vec![0, 1]);
+    assert_eq!(ranking["sleep"].dev, vec![0]);
+}
+
+#[test]
+fn test_count_wins() {
+    let runs = vec![test_run(20., 0.2), test_run(10., 0.1), test_run(10., 0.1)];
+    let wins = count_wins(&rank_runs(&runs, 2.), runs.len());
+
+    assert_eq!(wins[0], Wins::default());
+    // both fastest sets share the win, the relative deviation is equal for everyone
+    assert_eq!(wins[1], Wins { avg: 1, min: 1, max: 1, dev: 0 });
+    assert_eq!(wins[1].total(), 3);
+    assert_eq!(wins[2], wins[1]);
+}
+
+#[test]
+fn test_percentiles() {
+    let values: Vec<f64> = (0..101).map(|v| v as f64).collect();
+    let stats = calc_statistics(&values, &Analysis::default());
+
+    assert_eq!(stats.median, 50.);
+    assert_eq!(stats.p5, 5.);
+    assert_eq!(stats.p25, 25.);
+    assert_eq!(stats.p75, 75.);
+    assert_eq!(stats.p95, 95.);
+    assert_eq!(stats.p99, 99.);
+    assert_eq!(stats.iqr, 50.);
+
+    let single = calc_statistics(&[3.], &Analysis::default());
+    assert_eq!(single.dev, 0.);
+    assert_eq!(single.p99, 3.);
+}
+
+#[test]
+fn test_outliers_tukey() {
+    // quartiles 10 and 11, mild fences at 8.5 and 12.5, severe ones at 7 and 14
+    let values = vec![10., 10., 10.5, 11., 11., 13., 30., 10.5, 8.];
+    let outliers = classify_outliers(&values, OutlierMethod::Tukey);
+    assert_eq!(outliers[5], Outlier::Mild);
+    assert_eq!(outliers[6], Outlier::Severe);
+    assert_eq!(outliers[8], Outlier::Mild);
+    assert_eq!(outliers.iter().filter(|&&o| o == Outlier::No).count(), 6);
+}
+
+#[test]
+fn test_outliers_mad() {
+    let values = vec![10., 10.1, 9.9, 10., 10.2, 9.8, 11., 30.];
+    let outliers = classify_outliers(&values, OutlierMethod::Mad);
+    assert_eq!(outliers[6], Outlier::Mild);
+    assert_eq!(outliers[7], Outlier::Severe);
+    assert_eq!(outliers[..6], [Outlier::No; 6]);
+
+    // without any spread there is nothing to measure the distance with
+    let outliers = classify_outliers(&[0., 0., 0., 0., 1.], OutlierMethod::Mad);
+    assert_eq!(outliers, vec![Outlier::No; 5]);
+}
+
+#[test]
+fn test_outliers_zero_iqr() {
+    let outliers = classify_outliers(&[0., 0., 0., 0., 1.], OutlierMethod::Tukey);
+    assert_eq!(outliers, vec![Outlier::No; 5]);
+    let outliers = classify_outliers(&[2., 2., 2.], OutlierMethod::Tukey);
+    assert_eq!(outliers, vec![Outlier::No; 3]);
+}
+
+#[test]
+fn test_exclude_outliers() {
+    let values = vec![1., 1.1, 0.9, 1., 1.05, 0.95, 3.];
+    let with = calc_statistics(&values, &Analysis::default());
+    assert_eq!(with.count, 7);
+    assert_eq!(with.max, 3.);
+    assert_eq!(with.severe_outliers, 1);
+
+    let without = calc_statistics(&values, &Analysis { exclude_outliers: true,
+                                                      ..Analysis::default() });
+    assert_eq!(without.count, 6);
+    assert_eq!(without.max, 1.1);
+    assert_eq!(without.severe_outliers, 1, "Outliers are still counted");
+}
+
+#[test]
+fn test_relative_ci() {
+    assert_eq!(relative_ci(&[1.]), f64::INFINITY);
+    assert_eq!(relative_ci(&[2., 2., 2.]), 0.);
+    let narrow = relative_ci(&[10., 10.1, 9.9, 10., 10.05, 9.95]);
+    let wide = relative_ci(&[10., 15., 5., 10., 12., 8.]);
+    assert!(narrow < 1.);
+    assert!(wide > narrow);
+}
+
+#[test]
+fn test_median() {
+    assert_eq!(median(&[3., 1., 2.]), 2.);
+    assert_eq!(median(&[4., 1., 2., 3.]), 2.5);
+}
+
+#[test]
+fn test_bootstrap_intervals() {
+    let values = vec![10., 11., 9., 10.5, 9.5, 10.2, 9.8, 10.1, 9.9, 10.];
+    let analysis = Analysis::default();
+    let stats = calc_statistics(&values, &analysis);
+
+    let (avg_ci, median_ci) = (stats.avg_ci.unwrap(), stats.median_ci.unwrap());
+    assert!(avg_ci.0 < stats.avg && stats.avg < avg_ci.1);
+    assert!(median_ci.0 <= stats.median && stats.median <= median_ci.1);
+    assert!(avg_ci.0 > 9. && avg_ci.1 < 11.);
+
+    // the same seed gives the same interval, a higher confidence a wider one
+    let again = calc_statistics(&values, &analysis);
+    assert_eq!(stats.avg_ci, again.avg_ci);
+    let wide = calc_statistics(&values, &Analysis { confidence: 99.9, ..analysis });
+    let wide = wide.avg_ci.unwrap();
+    assert!(wide.1 - wide.0 > avg_ci.1 - avg_ci.0);
+
+    let single = calc_statistics(&[2.], &Analysis::default());
+    assert_eq!(single.avg_ci, Some((2., 2.)));
+
+    let without = calc_statistics(&values, &Analysis::without_intervals());
+    assert_eq!((without.avg_ci, without.median_ci), (None, None));
 }
 
 #[test]
@@ -313,6 +1041,7 @@ fn test_relative_variance() {
         max: 0.,
         dev: 0.,
         count: 100,
+        ..Default::default()
     };
     assert_eq!(calc_relative_variance(&ez_stats), 0.);
 
@@ -333,11 +1062,181 @@ fn test_read_result() {
       - 1.5";
     let yaml = YamlLoader::load_from_str(result_str).unwrap();
 
-    let result = results_from_yaml(&yaml[0]);
+    let result = results_from_yaml(&yaml[0]).0;
+    let times = result.get("program1").unwrap().samples(WALL_TIME);
+
+    assert_eq!(times[0], 0.9);
+    assert_eq!(times[1], 1.1);
+    assert_eq!(times[2], 1.2);
+    assert_eq!(times[3], 1.3);
+    assert_eq!(times[4], 1.5);
+}
+
+#[test]
+fn test_read_result_with_warmup() {
+    use yaml_rust::YamlLoader;
+    let result_str = "---
+    - program1:
+        warmup: 2
+        times:
+          - 0.9
+          - 1.1
+    - program2:
+      - 1.5";
+    let yaml = YamlLoader::load_from_str(result_str).unwrap();
+
+    let result = results_from_yaml(&yaml[0]).0;
+
+    assert_eq!(result["program1"].samples(WALL_TIME), vec![0.9, 1.1]);
+    assert_eq!(result["program1"].warmup, 2);
+    assert_eq!(result["program2"].samples(WALL_TIME), vec![1.5]);
+}
+
+#[test]
+fn test_read_usage_lists() {
+    use yaml_rust::YamlLoader;
+    let result_str = "---
+    - program1:
+        times:
+          - 0.9
+          - 1.1
+        user_time:
+          - 0.8
+          - 1.0
+        max_rss:
+          - 1024
+          - 2048
+    - program2:
+      - 1.5";
+    let yaml = YamlLoader::load_from_str(result_str).unwrap();
+
+    let result = results_from_yaml(&yaml[0]).0;
+    let program1 = &result["program1"];
+
+    assert_eq!(program1.runs.len(), 2);
+    assert_eq!(program1.runs[1].value("user_time"), Some(1.0));
+    assert_eq!(program1.runs[1].metrics["max_rss"].unit, "kB");
+    assert_eq!(program1.samples(WALL_TIME), vec![0.9, 1.1]);
+
+    let stats = process_metrics(&result);
+    assert_eq!(stats["program1"]["max_rss"].max, 2048.);
+    assert!(!stats["program2"].contains_key("max_rss"));
+}
+
+#[test]
+fn test_read_runs() {
+    use yaml_rust::YamlLoader;
+    let result_str = "---
+    - program1:
+        warmup: 1
+        order: shuffled
+        seed: 1234
+        shell_startup: 0.002
+        parameters:
+          threads: \"4\"
+        units:
+          wall: s
+          iterations: it
+        runs:
+          - wall: 0.9
+            iterations: 10
+          - wall: 1.1
+            iterations: 12
+            cpus: \"2-3\"";
+    let yaml = YamlLoader::load_from_str(result_str).unwrap();
+
+    let result = results_from_yaml(&yaml[0]).0;
+    let program1 = &result["program1"];
+
+    assert_eq!(program1.warmup, 1);
+    assert_eq!(program1.samples(WALL_TIME), vec![0.9, 1.1]);
+    assert_eq!(program1.samples("iterations"), vec![10., 12.]);
+    assert_eq!(program1.runs[0].metrics["iterations"].unit, "it");
+    assert_eq!(program1.parameters["threads"], "4");
+    assert_eq!(program1.order, Some(RunOrder::Shuffled(1234)));
+    assert_eq!(program1.runs[1].cpus, vec![2, 3]);
+    assert_eq!(program1.shell_startup, Some(0.002));
+    assert!(!program1.runs[1].metrics.contains_key("cpus"));
+}
+
+#[test]
+fn test_read_versioned_results() {
+    use yaml_rust::YamlLoader;
+    let result_str = "---
+    format_version: 2
+    metadata:
+      macrobm_version: 0.5.0
+      timestamp: 2024-01-31T12:00:00Z
+      hostname: bench01
+      kernel: Linux 6.1.0
+      cpu_model: Some CPU
+      cores: 8
+      jobs: 2
+      config_file: benchmarks.yml
+      order: shuffled
+      seed: 42
+    cases:
+      - program1:
+          warmup: 0
+          config:
+            name: program1
+            command: ./program1
+          units:
+            wall: s
+          runs:
+            - wall: 0.9
+              outcome: exited
+              exit_code: 0
+            - outcome: timed_out
+            - outcome: invalid
+              signal: 11
+              error: stdout does not match `ok`";
+    let yaml = YamlLoader::load_from_str(result_str).unwrap();
+
+    let (result, metadata) = results_from_yaml(&yaml[0]);
+    let metadata = metadata.unwrap();
+    let program1 = &result["program1"];
+
+    assert_eq!(metadata.hostname, "bench01");
+    assert_eq!((metadata.cores, metadata.jobs), (8, 2));
+    assert_eq!(metadata.git_commit, None);
+    assert_eq!(program1.order, Some(RunOrder::Shuffled(42)));
+    assert_eq!(program1.config.as_ref().unwrap()["command"].as_str(), Some("./program1"));
+    assert_eq!(program1.samples(WALL_TIME), vec![0.9]);
+    assert_eq!(program1.runs.len(), 3);
+    assert_eq!(program1.runs[0].status.as_ref().unwrap().exit_code, Some(0));
+    assert_eq!(program1.runs[1].status.as_ref().unwrap().outcome, RunOutcome::TimedOut);
+    let invalid = program1.runs[2].status.as_ref().unwrap();
+    assert_eq!((invalid.signal, invalid.message.as_ref().unwrap().as_str()),
+               (Some(11), "stdout does not match `ok`"));
+    assert!(program1.runs[2].metrics.is_empty());
+}
+
+#[test]
+fn test_read_json_results() {
+    let result_str = r#"{
+      "format_version": 2,
+      "cases": [
+        {"program1": {"warmup": 1, "units": {"wall": "s"},
+                      "runs": [{"wall": 0.9, "outcome": "exited", "exit_code": 0},
+                               {"wall": 1, "outcome": "failed", "exit_code": 2}]}}
+      ]
+    }"#;
+    let doc = json::parse(result_str).unwrap();
+
+    let (result, metadata) = results_from_yaml(&doc);
+    let program1 = &result["program1"];
 
-    assert_eq!(result.get("program1").unwrap()[0], 0.9);
-    assert_eq!(result.get("program1").unwrap()[1], 1.1);
-    assert_eq!(result.get("program1").unwrap()[2], 1.2);
-    assert_eq!(result.get("program1").unwrap()[3], 1.3);
-    assert_eq!(result.get("program1").unwrap()[4], 1.5);
+    assert_eq!(metadata, None);
+    assert_eq!(program1.warmup, 1);
+    assert_eq!(program1.samples(WALL_TIME), vec![0.9, 1.]);
+    assert_eq!(program1.failures(), 1);
+}
+
+#[test]
+#[should_panic(expected = "format version 3")]
+fn test_read_newer_format_version() {
+    use yaml_rust::YamlLoader;
+    let yaml = YamlLoader::load_from_str("format_version: 3\ncases: []").unwrap();
+    results_from_yaml(&yaml[0]);
 }
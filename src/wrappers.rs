@@ -1,129 +1,573 @@
-/// Provide wrappers for toplevel operations that require multiple
-/// modules to work together.
+//! Provide wrappers for toplevel operations that require multiple
+//! modules to work together.
 
 use messages;
 use config;
 use benchmarking;
+use benchmarking::Outcome;
+use results;
+use results::{ResultSet, RunRecord};
+use metadata::Metadata;
 use statistics;
+use statistics::Analysis;
+use requirements;
+use significance::Test;
+use affinity;
+use affinity::CpuSlots;
+use journal;
+use journal::Journal;
+use export;
+use export::Format;
 
 // Sender and Receiver live on the channel.
 use threadpool::ThreadPool;
-use std::sync::mpsc::{Sender, Receiver, channel};
+use std::sync::mpsc::{Sender, Receiver, RecvTimeoutError, channel};
+use std::sync::Arc;
 
 // timepoints for time measurements
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use std::fs;
+use std::io;
 
 // save results in hashmap
 use std::collections::BTreeMap;
 
 
-/// Do all the benchmarks that are configured via the .yml file.
-pub fn benchmarking_process(cfg_file: &str, threads: usize,
+/// Do all the benchmarks that are configured via the .yml file. With `exclusive_cpus` every
+/// thread gets its own set of CPUs. Every finished run is appended to the journal of the result
+/// file, with `resume` the runs of the journal are kept and only the missing ones are done.
+/// Returns 130 if the session was interrupted.
+pub fn benchmarking_process(cfg_file: &str, threads: usize, exclusive_cpus: bool, resume: bool,
                             res_file: &str) -> i32 {
     // Read configuration for the benchmarks
     let bm_cfg = config::parse_config_file(cfg_file);
 
+    let journal_file = journal::journal_file(res_file);
+    let (done, metadata) = if resume {
+        let (done, metadata) = journal::read_journal(&journal_file);
+        messages::resume(&journal_file, done.values().map(|c| c.runs.len()).sum());
+        // the session keeps the metadata of its start
+        let metadata = metadata.unwrap_or_else(|| {
+            Metadata::collect(cfg_file, threads, bm_cfg.order)
+        });
+        (done, metadata)
+    } else {
+        (ResultSet::new(), Metadata::collect(cfg_file, threads, bm_cfg.order))
+    };
+    let mut journal = match resume {
+        true => Journal::append_to(&journal_file),
+        false => Journal::create(&journal_file, &metadata),
+    }.unwrap_or_else(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => {
+            panic!("{} of an interrupted session exists, continue it with --resume or remove it!",
+                   journal_file)
+        }
+        _ => panic!("Could not open the journal {}: {}", journal_file, e),
+    });
+    let missing = remaining_session(&bm_cfg, &done);
+    let progress = adaptive_progress(&missing, &done);
+
     // The channel is used to communicate the results of each benchmark.
     let (tx, rx) = channel();
 
     // start timer to measure overall runtime
     let start_all = Instant::now();
 
+    // Ctrl-C stops the session, the runs done so far are still written
+    benchmarking::catch_interrupt();
     // Schedule all wanted commands n times in a threadpool of n_workers
     // threads.
-    let scheduled = schedule_benchmarks(bm_cfg, threads, tx);
+    let (planned, session) = schedule_benchmarks(&missing, &progress, threads, exclusive_cpus,
+                                                 tx);
     // Wait untill all scheduled commands are done and return the results.
-    let (stats, successes, fails) = collect_results(scheduled, rx);
+    let (mut results, mut summary) = collect_results(done, planned, &rx, &mut journal);
+    if summary.interrupted {
+        // runs that are still going are not measured properly, but every case is cleaned up
+        for (name, msg) in session.stop() {
+            *summary.errors.entry(name).or_default().entry(msg).or_insert(0) += 1;
+        }
+    }
+    for (name, case) in results.iter_mut() {
+        // cases of the journal that are not configured anymore keep what they know
+        if let Some(config) = bm_cfg.cases.get(name) {
+            case.parameters = config.parameters.clone();
+            case.config = Some(config::config_to_yaml(config));
+        }
+        case.order = Some(bm_cfg.order);
+    }
 
     // report the time and state of all benchmarks
-    messages::report_runinformation(start_all.elapsed(), successes, fails);
+    messages::report_runinformation(start_all.elapsed(), &summary);
 
     // report detailed benchmark statistics for each case
-    report_data(&stats);
-    messages::write_result_file(&res_file, &stats);
+    report_data(&results, results::WALL_TIME, &Analysis::default(),
+                messages::DEFAULT_COLUMNS);
+    messages::write_result_file(res_file, &results, &metadata);
 
+    if summary.interrupted {
+        messages::interrupted(res_file, &journal_file);
+        return 130;
+    }
+    // the result file is complete, the journal is not needed anymore
+    let _ = fs::remove_file(&journal_file);
     0
 }
 
-/// Define the process of reporting the results of a benchmark.
-pub fn reporting_process(result_file: &str) -> i32 {
-    let bm_statistics = statistics::read_result_from_file(result_file);
-    report_data(&bm_statistics)
+/// The part of the session that is still missing after the runs that are `done`. Cases only
+/// do the runs they lack, cases that are complete are left out. The warmups are all done again,
+/// a resumed session starts with cold caches. Adaptive cases keep their configuration, they
+/// continue from their `adaptive_progress`.
+fn remaining_session(bm_cfg: &config::SessionConfig, done: &ResultSet) -> config::SessionConfig {
+    let mut cases = BTreeMap::new();
+
+    for (name, config) in &bm_cfg.cases {
+        let mut config = config.clone();
+        if let Some(case) = done.get(name) {
+            let runs = case.runs.len() as i64;
+            if config.planned_runs() <= runs {
+                continue;
+            }
+            if !config.is_adaptive() {
+                config.count -= runs;
+            }
+        }
+        cases.insert(name.clone(), config);
+    }
+    config::SessionConfig { cases, order: bm_cfg.order }
+}
+
+/// Where the adaptive cases of the `session` stand after the runs that are `done`: the
+/// measurements that are part of the statistics and the time the runs took.
+fn adaptive_progress(session: &config::SessionConfig, done: &ResultSet)
+    -> BTreeMap<String, benchmarking::AdaptiveProgress> {
+    let mut progress = BTreeMap::new();
+
+    for (name, config) in &session.cases {
+        let case = match done.get(name) {
+            Some(case) if config.is_adaptive() => case,
+            _ => continue,
+        };
+        let seconds: f64 = case.runs.iter().filter_map(|r| r.value(results::WALL_TIME)).sum();
+        progress.insert(name.clone(), benchmarking::AdaptiveProgress {
+            samples: case.runs.iter()
+                .filter(|r| r.succeeded())
+                .filter_map(|r| r.value(results::WALL_TIME))
+                .collect(),
+            runs: case.runs.len(),
+            spent: Duration::from_secs_f64(seconds),
+        });
+    }
+    progress
+}
+
+/// Define the process of reporting the results of a benchmark. With `group_by` the cases are
+/// reported in groups that share the value of that matrix parameter. With a `format` only the
+/// statistics are printed in that format.
+pub fn reporting_process(result_file: &str, metric: &str, group_by: Option<&str>,
+                         analysis: &Analysis, columns: &[&str], format: Option<Format>) -> i32 {
+    let (bm_results, metadata) = statistics::read_results_with_metadata(result_file);
+    check_metric(result_file, &bm_results, metric);
+    let analysis = &shown_analysis(analysis, columns);
+    if let Some(format) = format {
+        let runs = analysed_runs(&bm_results, analysis);
+        let stats = statistics::process_results(&results::samples(&runs, metric), analysis);
+        messages::write_export(None, &export::statistics(&stats, &results::failures(&bm_results),
+                                                         metric, analysis.confidence, columns,
+                                                         format));
+        return 0;
+    }
+    if let Some(ref metadata) = metadata {
+        messages::report_metadata(result_file, metadata);
+    }
+
+    match group_by {
+        Some(parameter) => {
+            let mut return_code = 0;
+            for (value, group) in results::group_by(&bm_results, parameter) {
+                messages::group_heading(parameter, &value);
+                return_code = return_code.max(report_data(&group, metric, analysis, columns));
+            }
+            return_code
+        }
+        None => report_data(&bm_results, metric, analysis, columns),
+    }
+}
+
+/// Export all runs of a result file, to `out_file` or to stdout without one.
+pub fn export_process(result_file: &str, format: Format, out_file: Option<&str>) -> i32 {
+    let (bm_results, metadata) = statistics::read_results_with_metadata(result_file);
+    messages::write_export(out_file, &export::runs(&bm_results, metadata.as_ref(), format));
+    0
 }
 
 /// Define the process of calculating and reporting the difference between
-/// multiple benchmark runs.
-pub fn diff_process(ground_truth: &str, results: &str, tolerance: f64) -> i32 {
-    let gt_stats = statistics::read_result_from_file(ground_truth);
-    let re_stats = statistics::read_result_from_file(results);
+/// multiple benchmark runs. With a `test` only significant differences count.
+pub fn diff_process(ground_truth: &str, results: &str, tolerance: f64,
+                    metric: &str, test: Option<Test>, alpha: f64,
+                    analysis: &Analysis) -> i32 {
+    let gt_results = statistics::read_result_from_file(ground_truth);
+    let re_results = statistics::read_result_from_file(results);
+    check_metric(ground_truth, &gt_results, metric);
+    check_metric(results, &re_results, metric);
 
     messages::intro_diff(ground_truth, results);
-    report_diff(&gt_stats, &re_stats, tolerance)
+    report_diff(&gt_results, &re_results, tolerance, metric, test, alpha, analysis)
+}
+
+/// Rank the results of any number of benchmark runs against each other and show which one
+/// wins each case.
+pub fn who_wins_process(result_files: &[&str], tolerance: f64, metric: &str) -> i32 {
+    let stats: Vec<_> = result_files.iter()
+        .map(|file| {
+            let bm_results = statistics::read_result_from_file(file);
+            check_metric(file, &bm_results, metric);
+            let runs = results::without_failures(&bm_results);
+            statistics::process_results(&results::samples(&runs, metric),
+                                        &Analysis::without_intervals())
+        })
+        .collect();
+    let rankings = statistics::rank_runs(&stats, tolerance);
+
+    messages::report_who_wins(result_files, &rankings);
+    messages::report_scoreboard(result_files,
+                                &statistics::count_wins(&rankings, result_files.len()));
+    0
+}
+
+/// Check the results of a benchmark run against the requirements of every case. Cases without
+/// own requirements are checked against the global ones.
+/// Returns 1 if any requirement is not met, 0 otherwise.
+pub fn check_process(result_file: &str, requirements_file: &str, metric: &str) -> i32 {
+    let requirements = config::parse_requirements_file(requirements_file);
+    let bm_results = statistics::read_result_from_file(result_file);
+    check_metric(result_file, &bm_results, metric);
+    let runs = results::without_failures(&bm_results);
+    let stats = statistics::process_results(&results::samples(&runs, metric),
+                                            &Analysis::without_intervals());
+
+    // every baseline file is only read once
+    let mut baselines = BTreeMap::new();
+    let mut checks = BTreeMap::new();
+
+    let mut names: Vec<&String> = bm_results.keys().chain(requirements.cases.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    for name in names {
+        let reqs = requirements.cases.get(name).unwrap_or(&requirements.global);
+        let mut case_checks = Vec::new();
+
+        for requirement in reqs {
+            let baseline = match *requirement {
+                requirements::Requirement::MaxRegressionVs(ref file, _) => {
+                    let base_stats = baselines.entry(file.clone()).or_insert_with(|| {
+                        let runs = results::without_failures(
+                            &statistics::read_result_from_file(file));
+                        statistics::process_results(&results::samples(&runs, metric),
+                                                    &Analysis::without_intervals())
+                    });
+                    base_stats.get(name)
+                }
+                _ => None,
+            };
+            case_checks.push(requirements::check(requirement, stats.get(name), baseline));
+        }
+        if !case_checks.is_empty() {
+            checks.insert(name.clone(), case_checks);
+        }
+    }
+
+    messages::report_check(&checks);
+    let all_passed = checks.values().flat_map(|c| c.iter()).all(|c| c.passed);
+    if all_passed { 0 } else { 1 }
+}
+
+/// Stop with the metrics of the file if it does not know `metric`, a misspelled metric would
+/// report nothing at all. Files without any measurement have nothing to compare with.
+fn check_metric(result_file: &str, results: &ResultSet, metric: &str) {
+    let metrics = results::metrics(results);
+    if !metrics.is_empty() && !metrics.iter().any(|m| m == metric) {
+        panic!("Unknown metric {:?}, {} has {}!", metric, result_file, metrics.join(", "));
+    }
+}
+
+/// Report the results of a benchmark run for one metric, followed by the resource usage.
+fn report_data(results: &ResultSet, metric: &str, analysis: &Analysis,
+               columns: &[&str]) -> i32 {
+    let runs = analysed_runs(results, analysis);
+    let stats = statistics::process_results(&results::samples(&runs, metric), analysis);
+    let return_code = messages::report_statistics(&stats, &results::failures(results),
+                                                  analysis.confidence, columns);
+
+    // files without resource usage only know the wall clock time
+    let has_usage = runs.values().any(|c| c.units().len() > 1);
+    if has_usage {
+        messages::report_usage(&statistics::process_metrics(&runs));
+    }
+    return_code
 }
 
+/// The analysis for a table with `columns`, without the bootstrap if no interval is shown.
+fn shown_analysis(analysis: &Analysis, columns: &[&str]) -> Analysis {
+    if columns.iter().any(|&c| c == "avg_ci" || c == "median_ci") {
+        analysis.clone()
+    } else {
+        Analysis { resamples: 0, ..analysis.clone() }
+    }
+}
 
-/// Report the results of a benchmark run.
-fn report_data(times: &BTreeMap<String, Vec<f32>>) -> i32 {
-    let stats = statistics::process_results(times);
-    messages::report_statistics(&stats)
+/// Runs the statistics are calculated from. Runs that did not succeed only count if the
+/// analysis includes them.
+fn analysed_runs(results: &ResultSet, analysis: &Analysis) -> ResultSet {
+    if analysis.include_failed {
+        results.clone()
+    } else {
+        results::without_failures(results)
+    }
+}
+
+/// Samples of every case a significance test compares, the same the statistics are calculated
+/// from.
+fn tested_samples(samples: &BTreeMap<String, Vec<f64>>,
+                  analysis: &Analysis) -> BTreeMap<String, Vec<f64>> {
+    if !analysis.exclude_outliers {
+        return samples.clone();
+    }
+    samples.iter()
+        .map(|(name, values)| {
+            (name.clone(), statistics::without_outliers(values, analysis.outliers))
+        })
+        .collect()
 }
 
 /// Report the difference between two benchmark results.
-fn report_diff(ground_truth: &BTreeMap<String, Vec<f32>>,
-               results: &BTreeMap<String, Vec<f32>>,
-               tolerance: f64) -> i32 {
-    let gt_stats = statistics::process_results(ground_truth);
-    let re_stats = statistics::process_results(results);
+fn report_diff(ground_truth: &ResultSet, new_results: &ResultSet,
+               tolerance: f64, metric: &str, test: Option<Test>, alpha: f64,
+               analysis: &Analysis) -> i32 {
+    let gt_samples = results::samples(&analysed_runs(ground_truth, analysis), metric);
+    let re_samples = results::samples(&analysed_runs(new_results, analysis), metric);
+    let gt_stats = statistics::process_results(&gt_samples, analysis);
+    let re_stats = statistics::process_results(&re_samples, analysis);
 
-    messages::report_diff(&gt_stats, &re_stats, tolerance)
+    let mut comparison = statistics::compare_runs(&gt_stats, &re_stats, tolerance);
+    if let Some(test) = test {
+        statistics::test_significance(&mut comparison, &tested_samples(&gt_samples, analysis),
+                                      &tested_samples(&re_samples, analysis), test, alpha);
+    }
+
+    messages::report_diff(&gt_stats, &re_stats, &comparison, &results::failures(ground_truth),
+                          &results::failures(new_results), analysis.confidence)
 }
 
 /// This function schedules all benchmarks that are supposed to run
-/// several times and distributes them over `n_workers` threads.
-fn schedule_benchmarks(bm_cfg: BTreeMap<String, benchmarking::RunConfig>,
+/// several times and distributes them over `n_workers` threads in the configured order.
+/// Adaptive cases continue from their `progress`. Returns the number of runs that are done at
+/// most and the session to stop it after an interrupt.
+fn schedule_benchmarks(bm_cfg: &config::SessionConfig,
+                       progress: &BTreeMap<String, benchmarking::AdaptiveProgress>,
                        n_workers: usize,
+                       exclusive_cpus: bool,
                        tx: Sender<benchmarking::Report>
-                      ) -> i64 {
+                      ) -> (i64, benchmarking::Session) {
     // --------------- Banner Message
     messages::intro(n_workers);
+    messages::run_order(bm_cfg.order);
+
+    let slots = if exclusive_cpus {
+        let cpus = affinity::available_cpus();
+        match CpuSlots::split(&cpus, n_workers) {
+            Some(slots) => {
+                messages::exclusive_cpus(cpus.len() / n_workers);
+                Some(Arc::new(slots))
+            }
+            None => panic!("{} threads need at least as many CPUs, only {} are available!",
+                           n_workers, cpus.len()),
+        }
+    } else {
+        None
+    };
 
     let pool = ThreadPool::new(n_workers);
-    let mut scheduled = 0;
+    let mut planned = 0;
 
-    for (name, config) in &bm_cfg {
-        messages::scheduled_command(&name, config.count);
-        benchmarking::do_benchmark(&pool, &name, tx.clone(), config);
-        scheduled += config.count;
+    for (name, config) in &bm_cfg.cases {
+        messages::scheduled_command(name, config);
+        let done = progress.get(name).map(|p| p.runs as i64).unwrap_or(0);
+        planned += config.planned_runs() - done + config.warmup.max(0);
     }
-    scheduled
+    let session = benchmarking::do_benchmarks(&pool, &bm_cfg.cases, progress, bm_cfg.order,
+                                              slots, tx);
+    (planned, session)
 }
 
-/// Collect all results for the benchmarks that were scheduled and return
-/// the statistical data.
-fn collect_results(scheduled: i64, rx: Receiver<benchmarking::Report>
-                  ) -> (BTreeMap<String, Vec<f32>>, i64, i64) {
-    let mut stats = BTreeMap::<String, Vec<f32>>::new();
+/// Count how the runs of a benchmark session ended.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub successes: i64,
+    pub fails: i64,
+    pub timeouts: i64,
+    /// Adaptive cases and why they stopped.
+    pub stops: BTreeMap<String, benchmarking::Stop>,
+    /// Cases that could not be run, with every distinct error and how often it occured.
+    pub errors: BTreeMap<String, BTreeMap<String, i64>>,
+    /// Cases with runs that did not meet the expectations, with every distinct reason.
+    pub invalid: BTreeMap<String, BTreeMap<String, i64>>,
+    /// The session was stopped with Ctrl-C before all runs were done.
+    pub interrupted: bool,
+}
 
-    // ------------- Wait for all bm to finish and notice the user about the state of the program.
-    let mut successes = 0;
-    let mut fails = 0;
+/// How often the collection checks for an interrupt while it waits for runs.
+const INTERRUPT_POLL: Duration = Duration::from_millis(100);
 
-    for finished in 0..scheduled {
-        let report = rx.recv().unwrap();
+/// Collect all results for the benchmarks that were scheduled and add them to `results`. Every
+/// report is appended to the `journal` as soon as it arrives. Adaptive cases decide while
+/// running how many runs they need, so this waits until every run is done and the channel is
+/// closed, or until the session is interrupted. `planned` is only the maximum for the progress
+/// display.
+fn collect_results(mut results: ResultSet, planned: i64, rx: &Receiver<benchmarking::Report>,
+                   journal: &mut Journal) -> (ResultSet, RunSummary) {
+    // ------------- Wait for all bm to finish and notice the user about the state of the program.
+    let mut summary = RunSummary::default();
+    let mut finished = 0;
 
-        // process report
-        stats.entry(report.name.clone()).or_insert(Vec::<f32>::new())
-            .push(report.duration);
+    loop {
+        // runs that end because of the interrupt were not measured properly
+        if benchmarking::interrupted() {
+            summary.interrupted = true;
+            break;
+        }
+        let report = match rx.recv_timeout(INTERRUPT_POLL) {
+            Ok(report) => report,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if benchmarking::interrupted() {
+            continue;
+        }
+        journal.append(&report).expect("Could not write the journal");
 
         // output information
-        messages::finished_program(&report, finished + 1, scheduled);
+        finished += 1;
+        messages::finished_program(&report, finished, planned);
 
-        if report.ecode.success() {
-            successes += 1
-        } else {
-            fails += 1
+        if let Some(ref stop) = report.stop {
+            summary.stops.insert(report.name.clone(), stop.clone());
         }
+        // the run itself is kept, but the case did not clean up after it
+        for msg in &report.hook_errors {
+            *summary.errors.entry(report.name.clone()).or_default()
+                .entry(msg.clone()).or_insert(0) += 1;
+        }
+
+        if report.warmup {
+            // a case that can not start shows it already in its warmups
+            match report.outcome {
+                Outcome::SpawnError(ref msg) | Outcome::HookError(ref msg) => {
+                    *summary.errors.entry(report.name.clone()).or_default()
+                        .entry(msg.clone()).or_insert(0) += 1;
+                }
+                _ => {}
+            }
+            results.entry(report.name.clone()).or_default().warmup += 1;
+            continue;
+        }
+
+        // every run is recorded with how it ended, only runs that exited have measurements
+        let case = results.entry(report.name.clone()).or_default();
+        case.runs.push(RunRecord::from_report(&report));
+
+        match report.outcome {
+            Outcome::Exited(_) | Outcome::Failed(_) => {
+                case.shell_startup = report.shell_startup;
+
+                if let Outcome::Exited(_) = report.outcome {
+                    summary.successes += 1
+                } else {
+                    summary.fails += 1
+                }
+            }
+            Outcome::TimedOut => summary.timeouts += 1,
+            Outcome::SpawnError(ref msg) | Outcome::WaitError(ref msg) |
+            Outcome::HookError(ref msg) => {
+                *summary.errors.entry(report.name.clone()).or_default()
+                    .entry(msg.clone()).or_insert(0) += 1;
+            }
+            Outcome::Invalid(_, ref msg) => {
+                *summary.invalid.entry(report.name.clone()).or_default()
+                    .entry(msg.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+    if summary.interrupted {
+        messages::stopped();
+    } else {
+        messages::finished();
     }
-    messages::finished();
-    (stats, successes, fails)
+    // cases that only did warmup runs have nothing to report
+    results.retain(|_, case| !case.runs.is_empty());
+    (results, summary)
+}
+
+
+// --------------------- tests for the wrappers ---------------------------
+
+#[allow(dead_code)]
+fn test_session_config(name: &str, content: &str) -> config::SessionConfig {
+    use std::env;
+    use std::process;
+
+    let file = env::temp_dir().join(format!("macrobm_{}_{}.yml", name, process::id()));
+    fs::write(&file, content).unwrap();
+    let bm_cfg = config::parse_config_file(file.to_str().unwrap());
+    fs::remove_file(&file).unwrap();
+    bm_cfg
+}
+
+#[test]
+fn test_warmups_that_can_not_start() {
+    use std::env;
+    use std::process;
+
+    let bm_cfg = test_session_config("missing", "count: 2\nwarmup: 1\ncases:\n  \
+                                                 - name: missing\n    \
+                                                   command: /nonexistent/macrobm_command\n");
+    let journal_file = env::temp_dir().join(format!("macrobm_missing_{}.journal", process::id()));
+    let journal_file = journal_file.to_str().unwrap();
+    let mut journal = Journal::create(journal_file, &Metadata::default()).unwrap();
+
+    let (tx, rx) = channel();
+    let (planned, _session) = schedule_benchmarks(&bm_cfg, &BTreeMap::new(), 1, false, tx);
+    let (results, summary) = collect_results(ResultSet::new(), planned, &rx, &mut journal);
+    fs::remove_file(journal_file).unwrap();
+
+    assert_eq!(results["missing"].runs.len(), 2);
+    assert_eq!(results["missing"].warmup, 1);
+    assert_eq!(summary.errors["missing"].values().sum::<i64>(), 3);
+}
+
+#[test]
+fn test_remaining_session() {
+    use results::{CaseResult, WALL_TIME};
+
+    let bm_cfg = test_session_config("resume", "count: 3\nwarmup: 2\ncases:\n  \
+                                                - name: started\n    command: a\n  \
+                                                - name: complete\n    command: b\n  \
+                                                - name: missing\n    command: c\n");
+    let mut done = ResultSet::new();
+    for (name, runs) in &[("started", 1), ("complete", 3)] {
+        let mut case = CaseResult { warmup: 2, ..CaseResult::default() };
+        for _ in 0..*runs {
+            let mut run = RunRecord::default();
+            run.insert(WALL_TIME, 1., "s");
+            case.runs.push(run);
+        }
+        done.insert(name.to_string(), case);
+    }
+
+    let missing = remaining_session(&bm_cfg, &done);
+
+    assert_eq!(missing.cases.keys().collect::<Vec<_>>(), vec!["missing", "started"]);
+    assert_eq!(missing.cases["started"].count, 2);
+    assert_eq!(missing.cases["started"].warmup, 2);
+    assert_eq!(missing.cases["missing"].count, 3);
 }